@@ -0,0 +1,81 @@
+//! A stable facade over `Computer` for downstream crates. Programs go in as plain words, registers
+//! are named by `Reg`, and anything that could index out of bounds returns a `Result`
+//!
+//! ```
+//! use spiminator::api::{Machine, Reg};
+//!
+//! // addiu $t0, $zero, 20; addiu $t1, $t0, 22
+//! let mut machine = Machine::new(&[0x2408_0014, 0x2509_0016]);
+//! machine.set_register(Reg::A0, 7).unwrap();
+//! machine.step().unwrap();
+//! assert_eq!(machine.register(Reg::T0), 20);
+//! machine.run().unwrap();
+//! assert!(machine.finished());
+//! assert_eq!(machine.register(Reg::T1), 42);
+//! assert_eq!(machine.register(Reg::A0), 7);
+//! assert!(machine.set_register(Reg::Zero, 1).is_err());
+//! assert!(machine.fp_register(32).is_err());
+//! assert!(machine.load_word(0x1001_0002).is_err());
+//! ```
+
+use crate::emulator::{Computer, Insn};
+pub use crate::emulator::{InsnError, Reg, RunError, RunOutcome, StepResult};
+use std::io::{BufRead, Write};
+
+#[derive(Debug)]
+pub struct Machine {
+    computer: Computer,
+}
+
+impl Machine {
+    /// A machine whose text segment holds `words`, starting at the default text base
+    pub fn new(words: &[u32]) -> Self {
+        Self {
+            computer: Computer::new(words.iter().copied().map(Insn).collect()),
+        }
+    }
+
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.computer.set_input(input);
+    }
+
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.computer.set_output(out);
+    }
+
+    pub fn run(&mut self) -> Result<RunOutcome, RunError> {
+        self.computer.run()
+    }
+
+    pub fn step(&mut self) -> Result<StepResult, RunError> {
+        self.computer.step()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.computer.finished()
+    }
+
+    /// Address of the next instruction
+    pub fn pc(&self) -> u32 {
+        self.computer.pc_addr()
+    }
+
+    pub fn register(&self, reg: Reg) -> u32 {
+        self.computer.ru(reg)
+    }
+
+    /// Fails for `$zero`
+    pub fn set_register(&mut self, reg: Reg, val: u32) -> Result<(), InsnError> {
+        self.computer.set_register(reg, val)
+    }
+
+    /// Raw bits of `$f{idx}`
+    pub fn fp_register(&self, idx: usize) -> Result<u32, InsnError> {
+        self.computer.fpr(idx)
+    }
+
+    /// The word at the word-aligned `addr`
+    pub fn load_word(&self, addr: u32) -> Result<u32, InsnError> {
+        self.computer.load_word(addr)
+    }
+}
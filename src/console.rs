@@ -0,0 +1,198 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Where syscalls read program input from and send program output to. Defaults to stdin and
+/// stdout
+pub struct Console {
+    input: Input,
+    out: Box<dyn Write>,
+    /// Bytes written since the last newline
+    column: usize,
+    /// Everything read and written since recording started
+    log: Option<IoLog>,
+}
+
+enum Input {
+    /// Goes through `io::stdin()` on every read rather than holding its lock, so several consoles
+    /// and the host can share stdin
+    Stdin,
+    Reader(Box<dyn BufRead>),
+}
+
+impl Console {
+    pub fn new(input: impl BufRead + 'static, out: impl Write + 'static) -> Self {
+        Self {
+            input: Input::Reader(Box::new(input)),
+            out: Box::new(out),
+            column: 0,
+            log: None,
+        }
+    }
+
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Input::Reader(Box::new(input));
+    }
+
+    /// Starts the column count over, as if at the start of a line
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.out = Box::new(out);
+        self.column = 0;
+    }
+
+    /// Bytes written since the last newline, for tab expansion or wrapping. Counts bytes, not
+    /// characters, and doesn't treat `\r` or `\t` specially
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Starts a new `IoLog` of everything read and written, dropping any previous one
+    pub fn start_recording(&mut self) {
+        self.log = Some(IoLog::default());
+    }
+
+    /// Stops recording and returns what was recorded, if recording was on
+    pub fn take_recording(&mut self) -> Option<IoLog> {
+        self.log.take()
+    }
+
+    /// Reads from what `log` recorded as input, so a recorded run can be repeated
+    pub fn replay(&mut self, log: &IoLog) {
+        self.set_input(io::Cursor::new(log.input()));
+    }
+
+    /// Reads up to and including the next newline. Returns an empty string at end of input
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        match &mut self.input {
+            Input::Stdin => io::stdin().read_line(&mut line)?,
+            Input::Reader(input) => input.read_line(&mut line)?,
+        };
+        if let Some(log) = &mut self.log {
+            log.push(IoEvent::Read(line.as_bytes().to_vec()));
+        }
+        Ok(line)
+    }
+
+    /// Reads a single byte, or `None` at end of input
+    pub fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = match &mut self.input {
+            Input::Stdin => next_byte(&mut io::stdin().lock())?,
+            Input::Reader(input) => next_byte(input)?,
+        };
+        if let (Some(log), Some(b)) = (&mut self.log, byte) {
+            log.push(IoEvent::Read(vec![b]));
+        }
+        Ok(byte)
+    }
+}
+
+/// Everything a program read and printed through a `Console`, in order. Plain data, so it can be
+/// saved with a bug report and fed back with `Console::replay`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoLog {
+    /// Consecutive reads, or consecutive writes, are merged into one event
+    pub events: Vec<IoEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoEvent {
+    Read(Vec<u8>),
+    Wrote(Vec<u8>),
+}
+
+impl IoLog {
+    fn push(&mut self, event: IoEvent) {
+        match (self.events.last_mut(), event) {
+            (_, IoEvent::Read(bytes) | IoEvent::Wrote(bytes)) if bytes.is_empty() => {}
+            (Some(IoEvent::Read(last)), IoEvent::Read(more))
+            | (Some(IoEvent::Wrote(last)), IoEvent::Wrote(more)) => last.extend(more),
+            (_, event) => self.events.push(event),
+        }
+    }
+
+    /// All the input, concatenated
+    pub fn input(&self) -> Vec<u8> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                IoEvent::Read(bytes) => Some(bytes.as_slice()),
+                IoEvent::Wrote(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    }
+
+    /// All the output, concatenated
+    pub fn output(&self) -> Vec<u8> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                IoEvent::Wrote(bytes) => Some(bytes.as_slice()),
+                IoEvent::Read(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    }
+}
+
+fn next_byte(input: &mut impl BufRead) -> io::Result<Option<u8>> {
+    let byte = input.fill_buf()?.first().copied();
+    if byte.is_some() {
+        input.consume(1);
+    }
+    Ok(byte)
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            input: Input::Stdin,
+            out: Box::new(io::stdout()),
+            column: 0,
+            log: None,
+        }
+    }
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        let written = &buf[..n];
+        if let Some(log) = &mut self.log {
+            log.push(IoEvent::Wrote(written.to_vec()));
+        }
+        self.column = match written.iter().rposition(|&b| b == b'\n') {
+            Some(newline) => n - newline - 1,
+            None => self.column + n,
+        };
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl fmt::Debug for Console {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Console").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_resets_after_newline() {
+        let mut console = Console::new(io::empty(), io::sink());
+        write!(console, "hello").unwrap();
+        assert_eq!(console.column(), 5);
+        write!(console, ", world").unwrap();
+        assert_eq!(console.column(), 12);
+        writeln!(console).unwrap();
+        assert_eq!(console.column(), 0);
+        write!(console, "ab\ncd\nxyz").unwrap();
+        assert_eq!(console.column(), 3);
+    }
+}
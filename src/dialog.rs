@@ -0,0 +1,120 @@
+use crate::console::Console;
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+/// Answers MARS's dialog syscalls. There's no GUI, so the default asks on the console; tests and
+/// front-ends can supply their own
+pub trait Dialogs: Debug {
+    /// `confirm_dialog`: a yes/no/cancel question
+    fn confirm(&mut self, console: &mut Console, message: &str) -> io::Result<Confirm>;
+
+    /// `input_dialog_int`
+    fn input_int(&mut self, console: &mut Console, message: &str) -> io::Result<IntInput>;
+
+    /// `message_dialog`
+    fn message(
+        &mut self,
+        console: &mut Console,
+        message: &str,
+        kind: MessageKind,
+    ) -> io::Result<()>;
+}
+
+/// Answers to a confirm dialog, in MARS's numbering
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Confirm {
+    Yes = 0,
+    No = 1,
+    Cancel = 2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IntInput {
+    Ok(i32),
+    /// Something was entered but it isn't an integer
+    Unparseable,
+    Cancelled,
+    /// OK was chosen with nothing entered
+    Empty,
+}
+
+impl IntInput {
+    /// The value and status MARS returns in `$a0` and `$a1`
+    pub fn registers(self) -> (i32, i32) {
+        match self {
+            IntInput::Ok(val) => (val, 0),
+            IntInput::Unparseable => (0, -1),
+            IntInput::Cancelled => (0, -2),
+            IntInput::Empty => (0, -3),
+        }
+    }
+}
+
+/// The icon MARS shows on a message dialog, from `$a1`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageKind {
+    Error,
+    Information,
+    Warning,
+    Question,
+    /// Any other `$a1`
+    Plain,
+}
+
+impl From<u32> for MessageKind {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => MessageKind::Error,
+            1 => MessageKind::Information,
+            2 => MessageKind::Warning,
+            3 => MessageKind::Question,
+            _ => MessageKind::Plain,
+        }
+    }
+}
+
+/// Turns each dialog into a prompt on the output and a line of input. End of input cancels
+#[derive(Debug, Default)]
+pub struct ConsoleDialogs;
+
+impl ConsoleDialogs {
+    fn ask(console: &mut Console, prompt: &str) -> io::Result<Option<String>> {
+        write!(console, "{prompt}")?;
+        console.flush()?;
+        let line = console.read_line()?;
+        Ok((!line.is_empty()).then(|| line.trim().to_string()))
+    }
+}
+
+impl Dialogs for ConsoleDialogs {
+    fn confirm(&mut self, console: &mut Console, message: &str) -> io::Result<Confirm> {
+        let answer = Self::ask(console, &format!("{message} [y/n] "))?;
+        Ok(match answer.map(|a| a.to_ascii_lowercase()).as_deref() {
+            Some("y" | "yes") => Confirm::Yes,
+            Some("n" | "no") => Confirm::No,
+            _ => Confirm::Cancel,
+        })
+    }
+
+    fn input_int(&mut self, console: &mut Console, message: &str) -> io::Result<IntInput> {
+        Ok(match Self::ask(console, &format!("{message} "))? {
+            None => IntInput::Cancelled,
+            Some(line) if line.is_empty() => IntInput::Empty,
+            Some(line) => line.parse().map_or(IntInput::Unparseable, IntInput::Ok),
+        })
+    }
+
+    fn message(
+        &mut self,
+        console: &mut Console,
+        message: &str,
+        kind: MessageKind,
+    ) -> io::Result<()> {
+        let prefix = match kind {
+            MessageKind::Error => "error: ",
+            MessageKind::Warning => "warning: ",
+            MessageKind::Information | MessageKind::Question | MessageKind::Plain => "",
+        };
+        writeln!(console, "{prefix}{message}")
+    }
+}
@@ -1,86 +1,1082 @@
+use crate::clock::{Clock, SystemClock};
+use crate::console::{Console, IoLog};
+use crate::dialog::{ConsoleDialogs, Dialogs};
 use num_enum::{TryFromPrimitive, UnsafeFromPrimitive};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
 use std::mem::transmute;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// SPIM's default address of the first instruction
+pub const TEXT_BASE: u32 = 0x00400000;
+
+/// SPIM's default address of the start of `.data`, where `load_data` puts the data segment unless
+/// told otherwise
+pub const DATA_BASE: u32 = 0x10010000;
+
+/// Where `with_args` puts the `argv` array, followed by the argument strings. Just below SPIM's
+/// initial stack pointer
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub const ARGV_BASE: u32 = 0x7FFF_F000;
+
+/// What registers hold before their first write when poisoning is on
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub const POISON: u32 = 0xDEADBEEF;
+
+/// How many recent pcs `RunError::backtrace` keeps
+pub const BACKTRACE_LEN: usize = 16;
+
+/// How many instructions `run_with_timeout` runs between reads of the clock
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
 
 #[derive(Debug)]
-struct Computer {
+pub struct Computer {
     regs: [u32; 32],
+    /// Bit `n` is set once register `n` has been written
+    written: u32,
+    /// Whether reading a never-written register is an error
+    poison_regs: bool,
+    /// Whether words that don't decode run as `nop`s instead of failing
+    lenient_decode: bool,
+    /// Addresses of the undecodable words run as `nop`s, in execution order
+    skipped: Vec<u32>,
+    /// Whether writing `$zero` raises `RegMutZero` rather than being ignored
+    strict_zero: bool,
+    /// Whether stores may overwrite the program
+    self_modifying: bool,
+    /// Whether executing an overwritten instruction raises `StaleInstructionCache`
+    detect_stale_code: bool,
+    /// Text addresses stores have overwritten, with the instruction loaded there, so `reset` can
+    /// put the program back
+    overwritten: HashMap<u32, Insn>,
+    /// Word address of the last `ll`, until a store to that word or an `sc` breaks the link
+    link: Option<u32>,
+    /// Whether `mul` raises `IntegerOverflow` when the product doesn't fit in 32 bits
+    strict_mul: bool,
+    /// Whether loading a word nothing has written raises `UninitializedMemoryRead`
+    strict_memory: bool,
+    /// What `read_int`/`read_char` do at end of input
+    eof_policy: EofPolicy,
+    /// What `read_int` does with a line that isn't a number
+    bad_int_policy: BadIntPolicy,
+    /// Whether `print_char` prints `$a0` as a Unicode scalar rather than a byte
+    unicode_print_char: bool,
+    /// Which `$v0` selects which syscall
+    syscalls: SyscallTable,
+    /// High and low words of multiply results
+    hi: u32,
+    lo: u32,
+    /// Coprocessor 1 registers, stored as raw bits
+    f_regs: [u32; 32],
+    /// Set by FP comparisons, read by `bc1t`/`bc1f`
+    fp_cond: bool,
+    /// How inexact FP conversions round
+    rounding: RoundingMode,
     program: Vec<Insn>,
     /// Direct index into `program`, not a byte offset
     pc: usize,
+    /// Set by the exit syscalls
+    exited: bool,
+    /// `$a0` of `exit2`; 0 otherwise
+    exit_code: i32,
+    /// Breakpoint address `run_until_breakpoint` last stopped on, so resuming doesn't stop there
+    /// again immediately
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    stopped_at: Option<u32>,
+    /// Keyed by word-aligned address; missing words read as 0
     mem: HashMap<u32, u32>,
+    /// Loads and stores made since the last `drain_mem_trace`, when tracing is on
+    mem_trace: Option<Vec<MemAccess>>,
+    /// Byte order within each word of `mem`
+    endianness: Endianness,
+    /// Address of `program[0]`
+    text_base: u32,
+    /// Where `load_data` puts the data segment
+    data_base: u32,
+    /// Set by `load_data`, kept so `reset` can lay it out again
+    data: Vec<u8>,
+    /// Set by `with_args`, kept so `reset` can lay them out again
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    args: Vec<String>,
+    console: Console,
+    clock: Box<dyn Clock>,
+    dialogs: Box<dyn Dialogs>,
+    /// Instructions completed so far
+    executed: u64,
+    /// Set from another thread to stop the run
+    cancel: Option<Arc<AtomicBool>>,
+    /// Estimated cycles for the instructions completed so far, per `costs`
+    cycles: u64,
+    costs: CycleCosts,
+    /// Addresses of the last `BACKTRACE_LEN` instructions started, oldest first
+    backtrace: VecDeque<u32>,
+    /// How many pcs `recent_pcs` keeps; 0 turns recording off
+    pc_history_len: usize,
+    /// The recorded pcs are the last `pc_history_len` entries. Grows to twice that before the
+    /// old half is dropped, so `recent_pcs` can hand out a slice
+    pc_history: Vec<u32>,
 }
 
 impl Computer {
-    fn run(&mut self) -> Result<(), InsnError> {
-        let mut exit = false;
-        while self.pc < self.program.len() && !exit {
-            self.try_handle_insn(self.program[self.pc], &mut exit)?;
+    pub fn new(program: Vec<Insn>) -> Self {
+        Self::with_text_base(program, TEXT_BASE)
+    }
+
+    /// Like `new`, but starts as if `main(argc, argv)` were called: `$a0` holds the number of
+    /// `args` and `$a1` points to a null-terminated array of pointers to them, at `ARGV_BASE`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn with_args(program: Vec<Insn>, args: &[&str]) -> Self {
+        let mut computer = Self::new(program);
+        computer.args = args.iter().map(|arg| arg.to_string()).collect();
+        computer.load_args();
+        computer
+    }
+
+    /// Lays out `args` at `ARGV_BASE` and points `$a0`/`$a1` at them
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    fn load_args(&mut self) {
+        if self.args.is_empty() {
+            return;
+        }
+        let args = self.args.clone();
+        let mut string = ARGV_BASE + (args.len() as u32 + 1) * 4;
+        for (n, arg) in args.iter().enumerate() {
+            let slot = ARGV_BASE + n as u32 * 4;
+            let stored = self.store_word(slot, string).and_then(|()| {
+                for (i, &b) in arg.as_bytes().iter().chain(&[0]).enumerate() {
+                    self.store_byte(string + i as u32, b)?;
+                }
+                Ok(())
+            });
+            stored.expect("argument strings overlap the text segment");
+            string += arg.len() as u32 + 1;
+        }
+        self.store_word(ARGV_BASE + args.len() as u32 * 4, 0)
+            .expect("argument strings overlap the text segment");
+        *self.ru_mut(Reg::A0).unwrap() = args.len() as u32;
+        *self.ru_mut(Reg::A1).unwrap() = ARGV_BASE;
+    }
+
+    pub fn with_text_base(program: Vec<Insn>, text_base: u32) -> Self {
+        Self {
+            regs: [0; 32],
+            written: 1,
+            poison_regs: false,
+            lenient_decode: false,
+            skipped: Vec::new(),
+            strict_zero: false,
+            self_modifying: false,
+            detect_stale_code: false,
+            overwritten: HashMap::new(),
+            link: None,
+            strict_mul: false,
+            strict_memory: false,
+            eof_policy: EofPolicy::default(),
+            bad_int_policy: BadIntPolicy::default(),
+            unicode_print_char: false,
+            syscalls: SyscallTable::default(),
+            hi: 0,
+            lo: 0,
+            f_regs: [0; 32],
+            fp_cond: false,
+            rounding: RoundingMode::default(),
+            program,
+            pc: 0,
+            exited: false,
+            exit_code: 0,
+            stopped_at: None,
+            mem: HashMap::new(),
+            mem_trace: None,
+            endianness: Endianness::default(),
+            text_base,
+            data_base: DATA_BASE,
+            data: Vec::new(),
+            args: Vec::new(),
+            console: Console::default(),
+            clock: Box::new(SystemClock),
+            dialogs: Box::new(ConsoleDialogs),
+            executed: 0,
+            cancel: None,
+            cycles: 0,
+            costs: CycleCosts::default(),
+            backtrace: VecDeque::with_capacity(BACKTRACE_LEN),
+            pc_history_len: 0,
+            pc_history: Vec::new(),
+        }
+    }
+
+    /// Puts the machine back in the state `new` left it in, keeping the program, console, clock
+    /// and settings, so the same program can be run again without rebuilding the `Computer`.
+    /// Instructions patched by self-modifying code are restored. Memory is cleared, then the
+    /// `load_data` image and `with_args` arguments are laid out again
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn reset(&mut self) {
+        self.regs = [0; 32];
+        self.written = 1;
+        self.set_poison_registers(self.poison_regs);
+        (self.hi, self.lo) = (0, 0);
+        self.f_regs = [0; 32];
+        self.fp_cond = false;
+        self.pc = 0;
+        self.exited = false;
+        self.exit_code = 0;
+        self.stopped_at = None;
+        self.link = None;
+        for (addr, insn) in std::mem::take(&mut self.overwritten) {
+            let idx = self
+                .text_index(addr)
+                .expect("patched address outside the program");
+            self.program[idx] = insn;
+        }
+        self.mem.clear();
+        self.executed = 0;
+        self.cycles = 0;
+        self.backtrace.clear();
+        self.pc_history.clear();
+        self.skipped.clear();
+        if let Some(trace) = &mut self.mem_trace {
+            trace.clear();
+        }
+        self.lay_out_data();
+        self.load_args();
+    }
+
+    /// Checks the program for problems that can be found without running it: words that aren't
+    /// instructions, branches that leave the text segment, and writes to `$zero` other than the
+    /// canonical `nop`. Reports every problem found
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        for (idx, insn) in self.program.iter().enumerate() {
+            let addr = self.pc_for_insn_index(idx as i64);
+            let decoded = decode(insn.0);
+            if let DecodedInsn::Unknown(word) = decoded {
+                errors.push(ValidationError::UnknownInstruction { addr, word });
+            }
+            if let Some(offset) = decoded.branch_offset() {
+                let target = idx as i64 + 1 + offset as i64;
+                if target < 0 || target > self.program.len() as i64 {
+                    let target = self.pc_for_insn_index(target);
+                    errors.push(ValidationError::BranchOutOfText { addr, target });
+                }
+            }
+            if let DecodedInsn::Jump { index, .. } = decoded {
+                let target = jump_target(addr, index);
+                if self.insn_index_for_pc(target).is_none() {
+                    errors.push(ValidationError::BranchOutOfText { addr, target });
+                }
+            }
+            if insn.0 != 0 && decoded.destination() == Some(Reg::Zero) {
+                errors.push(ValidationError::WriteToZero { addr });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.console.set_input(input);
+    }
+
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.console.set_output(out);
+    }
+
+    /// Bytes printed since the last newline
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn output_column(&self) -> usize {
+        self.console.column()
+    }
+
+    /// Records syscall input and output from now on, for `take_io_log`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn record_io(&mut self) {
+        self.console.start_recording();
+    }
+
+    /// Stops recording and returns the log, if `record_io` was called
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn take_io_log(&mut self) -> Option<IoLog> {
+        self.console.take_recording()
+    }
+
+    /// Feeds syscalls the input `log` recorded, in place of the current input
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn replay_io(&mut self, log: &IoLog) {
+        self.console.replay(log);
+    }
+
+    /// With poisoning on, registers that haven't been written hold `POISON` and reading one from
+    /// an instruction raises `UninitializedRegister`. Off by default, where registers start at 0
+    /// as in SPIM
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_poison_registers(&mut self, on: bool) {
+        self.poison_regs = on;
+        let fill = if on { POISON } else { 0 };
+        for (n, reg) in self.regs.iter_mut().enumerate() {
+            if self.written & (1 << n) == 0 {
+                *reg = fill;
+            }
+        }
+    }
+
+    /// With lenient decoding on, an unknown opcode, funct, REGIMM selector or coprocessor format
+    /// is executed as a `nop` and its address logged in `skipped`, rather than raising an error.
+    /// Useful for running binaries that use instructions the emulator doesn't support yet
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_lenient_decode(&mut self, on: bool) {
+        self.lenient_decode = on;
+    }
+
+    /// Addresses of the words lenient decoding has skipped, in execution order
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn skipped(&self) -> &[u32] {
+        &self.skipped
+    }
+
+    /// With strict zero on, instructions that write `$zero` raise `RegMutZero`. Off by default,
+    /// where the write is discarded as on real hardware, so `nop` and friends work
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_strict_zero(&mut self, on: bool) {
+        self.strict_zero = on;
+    }
+
+    /// Allows stores into the text segment, which then change the instructions executed. Off by
+    /// default, where they raise `WriteToTextSegment`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_self_modifying(&mut self, on: bool) {
+        self.self_modifying = on;
+    }
+
+    /// A debugging aid for self-modifying code: executing an instruction that a store has
+    /// overwritten raises `StaleInstructionCache` instead of running the new word. Off by default
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_detect_stale_code(&mut self, on: bool) {
+        self.detect_stale_code = on;
+    }
+
+    /// With strict `mul` on, a product that doesn't fit in 32 bits raises `IntegerOverflow`, like
+    /// `add`. Off by default, where the high bits are dropped
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_strict_mul(&mut self, on: bool) {
+        self.strict_mul = on;
+    }
+
+    /// With strict memory on, a load instruction from a word that nothing has stored to, or put
+    /// there with `load_memory` or `with_args`, raises `UninitializedMemoryRead`. Tracked per
+    /// word, so storing one byte initializes its whole word. The text segment always counts as
+    /// initialized. Off by default, where such words read as 0
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_strict_memory(&mut self, on: bool) {
+        self.strict_memory = on;
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_eof_policy(&mut self, policy: EofPolicy) {
+        self.eof_policy = policy;
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_bad_int_policy(&mut self, policy: BadIntPolicy) {
+        self.bad_int_policy = policy;
+    }
+
+    /// With Unicode `print_char` on, `$a0` is a code point printed as UTF-8, and values that
+    /// aren't Unicode scalars print as U+FFFD. Off by default, where only the low byte is written
+    /// as is, like SPIM
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_unicode_print_char(&mut self, on: bool) {
+        self.unicode_print_char = on;
+    }
+
+    /// Defaults to `SyscallTable::mars`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_syscall_table(&mut self, table: SyscallTable) {
+        self.syscalls = table;
+    }
+
+    /// Records the addresses of the last `len` instructions executed, for `recent_pcs`. Off (0)
+    /// by default
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_pc_history(&mut self, len: usize) {
+        self.pc_history_len = len;
+        self.pc_history = Vec::with_capacity(len * 2);
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_rounding_mode(&mut self, mode: RoundingMode) {
+        self.rounding = mode;
+    }
+
+    /// Records every load and store instruction for `drain_mem_trace`, e.g. to feed a cache
+    /// simulator. Off by default. Memory touched by syscalls isn't recorded
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_mem_trace(&mut self, on: bool) {
+        self.mem_trace = on.then(Vec::new);
+    }
+
+    /// The accesses recorded since the last call, oldest first
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn drain_mem_trace(&mut self) -> Vec<MemAccess> {
+        self.mem_trace
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// The `load_data` image and argument strings from `with_args` are rewritten in the new byte
+    /// order
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_endianness(&mut self, endianness: Endianness) {
+        self.endianness = endianness;
+        self.lay_out_data();
+        self.load_args();
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Answers the dialog syscalls. Defaults to `ConsoleDialogs`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_dialogs(&mut self, dialogs: impl Dialogs + 'static) {
+        self.dialogs = Box::new(dialogs);
+    }
+
+    pub fn run(&mut self) -> Result<RunOutcome, RunError> {
+        while !self.finished() {
+            self.exec_next()?;
+        }
+        Ok(RunOutcome {
+            exit_code: self.exit_code,
+            executed: self.executed,
+        })
+    }
+
+    /// Like `run`, but fails with `Timeout` once `limit` has passed on the computer's clock. The
+    /// clock is read every `TIMEOUT_CHECK_INTERVAL` instructions, so a syscall blocked on input
+    /// isn't interrupted; the timeout is noticed after it returns
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn run_with_timeout(&mut self, limit: Duration) -> Result<RunOutcome, RunError> {
+        let limit = limit.as_millis().try_into().unwrap_or(u64::MAX);
+        let deadline = self.clock.now_millis().saturating_add(limit);
+        while !self.finished() {
+            self.run_steps(TIMEOUT_CHECK_INTERVAL)?;
+            if !self.finished() && self.clock.now_millis() > deadline {
+                return Err(RunError {
+                    error: InsnError::Timeout,
+                    executed: self.executed,
+                    backtrace: self.backtrace().collect(),
+                });
+            }
+        }
+        Ok(RunOutcome {
+            exit_code: self.exit_code,
+            executed: self.executed,
+        })
+    }
+
+    /// Like `run`, but stops before executing any instruction whose address is in `bps`. Calling
+    /// this again resumes past the breakpoint that was just hit
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn run_until_breakpoint(&mut self, bps: &BTreeSet<u32>) -> Result<StopReason, RunError> {
+        let mut resume = self.stopped_at.take();
+        while !self.finished() {
+            let addr = self.pc_addr();
+            if resume.take() != Some(addr) && bps.contains(&addr) {
+                self.stopped_at = Some(addr);
+                return Ok(StopReason::Breakpoint(addr));
+            }
+            self.exec_next()?;
+        }
+        Ok(StopReason::Exited)
+    }
+
+    /// Executes at most `n` instructions, so a host can interleave several computers
+    pub fn run_steps(&mut self, n: u64) -> Result<StepResult, RunError> {
+        for _ in 0..n {
+            if self.finished() {
+                break;
+            }
+            self.exec_next()?;
         }
+        Ok(if self.finished() {
+            StepResult::Exited
+        } else {
+            StepResult::Running
+        })
+    }
+
+    pub fn step(&mut self) -> Result<StepResult, RunError> {
+        self.run_steps(1)
+    }
+
+    /// Like `step`, but also returns the disassembly of the instruction that ran, or an empty
+    /// string if the program had already finished
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn step_verbose(&mut self) -> Result<(String, StepResult), RunError> {
+        let text = self
+            .peek_insn()
+            .map_or_else(String::new, |insn| decode(insn.0).to_string());
+        Ok((text, self.step()?))
+    }
+
+    /// Like `step`, but returns the GPRs the instruction changed, with their values before and
+    /// after, for checking one instruction at a time. Empty if the program had already finished
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn step_delta(&mut self) -> Result<Vec<RegDiff>, RunError> {
+        let before = self.registers();
+        self.step()?;
+        Ok(before
+            .into_iter()
+            .filter(|&(reg, old)| self.ru(reg) != old)
+            .map(|(reg, old)| RegDiff {
+                reg,
+                old,
+                new: self.ru(reg),
+            })
+            .collect())
+    }
+
+    /// The instruction `step` would execute next, or `None` once the program is finished
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn peek_insn(&self) -> Option<Insn> {
+        if self.finished() {
+            None
+        } else {
+            Some(self.program[self.pc])
+        }
+    }
+
+    /// Whether the program called exit or ran past its last instruction
+    pub fn finished(&self) -> bool {
+        self.exited || self.pc >= self.program.len()
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Estimated cycles taken so far. Equals `executed` with the default costs
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Once `flag` is set, the next instruction fails with `Cancelled` instead of running. The
+    /// flag is left set; clear it before resuming
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_cycle_costs(&mut self, costs: CycleCosts) {
+        self.costs = costs;
+    }
+
+    /// Addresses of the most recent instructions, oldest first
+    pub fn backtrace(&self) -> impl Iterator<Item = u32> + '_ {
+        self.backtrace.iter().copied()
+    }
+
+    /// Addresses of up to the last `set_pc_history` instructions executed, oldest first
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn recent_pcs(&self) -> &[u32] {
+        let start = self.pc_history.len().saturating_sub(self.pc_history_len);
+        &self.pc_history[start..]
+    }
+
+    fn exec_next(&mut self) -> Result<(), RunError> {
+        if self.pc_history_len > 0 {
+            if self.pc_history.len() == self.pc_history_len * 2 {
+                self.pc_history.drain(..self.pc_history_len);
+            }
+            self.pc_history.push(self.pc_addr());
+        }
+        if self.backtrace.len() == BACKTRACE_LEN {
+            self.backtrace.pop_front();
+        }
+        self.backtrace.push_back(self.pc_addr());
+        let insn = self.program[self.pc];
+        let cancelled = self
+            .cancel
+            .as_ref()
+            .is_some_and(|f| f.load(Ordering::Relaxed));
+        let stale = self.detect_stale_code && self.overwritten.contains_key(&self.pc_addr());
+        let mut result = if cancelled {
+            Err(InsnError::Cancelled)
+        } else if stale {
+            Err(InsnError::StaleInstructionCache(self.pc_addr()))
+        } else {
+            self.try_handle_insn(insn)
+        };
+        if self.lenient_decode
+            && matches!(
+                result,
+                Err(InsnError::InvalidOpcode(_)
+                    | InsnError::UnimplementedOpcode(_)
+                    | InsnError::InvalidFunct(_)
+                    | InsnError::InvalidRegImm(_)
+                    | InsnError::InvalidFmt(_))
+            )
+        {
+            // Decoding fails before an instruction has any effect, so this is a clean nop
+            self.skipped.push(self.pc_addr());
+            self.pc += 1;
+            result = Ok(());
+        }
+        self.regs[0] = 0;
+        result.map_err(|error| RunError {
+            error,
+            executed: self.executed,
+            backtrace: self.backtrace().collect(),
+        })?;
+        self.executed += 1;
+        self.cycles += self.costs.cost(insn);
         Ok(())
     }
 
-    fn try_handle_insn(&mut self, insn: Insn, exit: &mut bool) -> Result<(), InsnError> {
+    /// Address of the instruction at `pc`. Wraps around the 32-bit address space like the hardware
+    pub fn pc_addr(&self) -> u32 {
+        self.pc_for_insn_index(self.pc as i64)
+    }
+
+    /// Address of `program[idx]`, or where it would be for indices outside the program. The
+    /// inverse of `insn_index_for_pc`
+    fn pc_for_insn_index(&self, idx: i64) -> u32 {
+        insn_addr(self.text_base, idx)
+    }
+
+    /// Index into `program` of `addr`, allowing one past the end like `branch`
+    fn insn_index_for_pc(&self, addr: u32) -> Option<usize> {
+        insn_index(self.text_base, addr).filter(|&idx| idx <= self.program.len())
+    }
+
+    /// Makes `pc` the next instruction to run, e.g. to start at `main` or for a debugger's "jump
+    /// to cursor". It must be a word-aligned address of an instruction in the text segment. A
+    /// program that exited resumes from there
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_pc(&mut self, pc: u32) -> Result<(), InsnError> {
+        if !pc.is_multiple_of(4) {
+            return Err(InsnError::UnalignedAccess(pc));
+        }
+        match self.insn_index_for_pc(pc) {
+            Some(idx) if idx < self.program.len() => {
+                self.pc = idx;
+                self.stopped_at = None;
+                self.exited = false;
+                self.exit_code = 0;
+                Ok(())
+            }
+            _ => Err(InsnError::BranchOutOfText(pc)),
+        }
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn text_base(&self) -> u32 {
+        self.text_base
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn data_base(&self) -> u32 {
+        self.data_base
+    }
+
+    fn try_handle_insn(&mut self, insn: Insn) -> Result<(), InsnError> {
         match insn.opcode()? {
             Opcode::Reg => match insn.funct()? {
-                Funct::Sll => *self.ru_mut(insn.rd())? = self.ru(insn.rt()) << insn.shamt(),
-                Funct::SllV => *self.ru_mut(insn.rd())? = self.ru(insn.rt()) << self.ru(insn.rs()),
-                Funct::Srl => *self.ru_mut(insn.rd())? = self.ru(insn.rt()) >> insn.shamt(),
-                Funct::SrlV => *self.ru_mut(insn.rd())? = self.ru(insn.rt()) >> self.ru(insn.rs()),
-                Funct::Sra => *self.ri_mut(insn.rd())? = self.ri(insn.rt()) >> insn.shamt(),
-                Funct::SraV => *self.ri_mut(insn.rd())? = self.ri(insn.rt()) >> self.ri(insn.rs()),
+                Funct::Sll => *self.ru_mut(insn.rd())? = self.read(insn.rt())? << insn.shamt(),
+                Funct::SllV => {
+                    *self.ru_mut(insn.rd())? =
+                        self.read(insn.rt())? << (self.read(insn.rs())? & 0x1F)
+                }
+                Funct::Srl => {
+                    let val = self.read(insn.rt())?;
+                    // MIPS32r2 reuses srl with bit 21 set as rotr
+                    *self.ru_mut(insn.rd())? = if insn.0 & (1 << 21) != 0 {
+                        val.rotate_right(insn.shamt())
+                    } else {
+                        val >> insn.shamt()
+                    };
+                }
+                Funct::SrlV => {
+                    let (val, amt) = (self.read(insn.rt())?, self.read(insn.rs())? & 0x1F);
+                    // ...and srlv with bit 6 set as rotrv
+                    *self.ru_mut(insn.rd())? = if insn.0 & (1 << 6) != 0 {
+                        val.rotate_right(amt)
+                    } else {
+                        val >> amt
+                    };
+                }
+                Funct::Sra => *self.ri_mut(insn.rd())? = self.read_i(insn.rt())? >> insn.shamt(),
+                Funct::SraV => {
+                    *self.ri_mut(insn.rd())? =
+                        self.read_i(insn.rt())? >> (self.read(insn.rs())? & 0x1F)
+                }
                 Funct::Syscall => {
-                    let code = SyscallCode::try_from_primitive(self.ru(Reg::V0))
-                        .map_err(|e| InsnError::UnsupportedSyscall(e.number))?;
+                    let number = self.read(Reg::V0)?;
+                    let code = self
+                        .syscalls
+                        .get(number)
+                        .ok_or(InsnError::UnsupportedSyscall(number))?;
                     match code {
-                        SyscallCode::Exit => *exit = true,
+                        SyscallCode::PrintInt => {
+                            let val = self.read_i(Reg::A0)?;
+                            write!(self.console, "{val}")?
+                        }
+                        SyscallCode::PrintString => {
+                            let bytes = self.load_string(self.read(Reg::A0)?);
+                            self.console.write_all(&bytes)?
+                        }
+                        SyscallCode::PrintFloat => {
+                            let val = format_float(self.fpr_f32(12)?);
+                            self.console.write_all(val.as_bytes())?
+                        }
+                        SyscallCode::PrintDouble => {
+                            let val = format_float(self.fpr_f64(12)?);
+                            self.console.write_all(val.as_bytes())?
+                        }
+                        SyscallCode::ReadInt => {
+                            self.console.flush()?;
+                            *self.ri_mut(Reg::V0)? = self.read_int()?;
+                        }
+                        SyscallCode::Exit => self.exited = true,
+                        SyscallCode::PrintChar => {
+                            let val = self.read(Reg::A0)?;
+                            if self.unicode_print_char {
+                                let ch = char::from_u32(val).unwrap_or(char::REPLACEMENT_CHARACTER);
+                                write!(self.console, "{ch}")?
+                            } else {
+                                self.console.write_all(&[val as u8])?
+                            }
+                        }
+                        SyscallCode::Exit2 => {
+                            self.exit_code = self.read_i(Reg::A0)?;
+                            self.exited = true;
+                        }
+                        SyscallCode::ReadChar => {
+                            self.console.flush()?;
+                            *self.ri_mut(Reg::V0)? = match self.console.read_byte()? {
+                                Some(b) => b as i32,
+                                None => self.eof_sentinel(0)?,
+                            };
+                        }
+                        SyscallCode::Time => {
+                            let now = self.clock.now_millis();
+                            *self.ru_mut(Reg::A0)? = now as u32;
+                            *self.ru_mut(Reg::A1)? = (now >> 32) as u32;
+                        }
+                        // No sound device; the pitch, duration, instrument and volume in $a0-$a3
+                        // are ignored
+                        SyscallCode::MidiOut => {}
+                        SyscallCode::ReadLine => {
+                            let (buf, len) = (self.read(Reg::A0)?, self.read(Reg::A1)?);
+                            self.console.flush()?;
+                            let line = self.console.read_line()?;
+                            // Like read_string, leave room for the terminating null
+                            let n = line.len().min(len.saturating_sub(1) as usize);
+                            let bytes = &line.as_bytes()[..n];
+                            for (i, &b) in bytes.iter().enumerate() {
+                                self.store_byte(buf.wrapping_add(i as u32), b)?;
+                            }
+                            if len > 0 {
+                                self.store_byte(buf.wrapping_add(n as u32), 0)?;
+                            }
+                            if self.read(Reg::A2)? != 0 {
+                                self.console.write_all(bytes)?;
+                            }
+                        }
+                        SyscallCode::Flush => self.console.flush()?,
+                        SyscallCode::ConfirmDialog => {
+                            let message = self.load_message()?;
+                            let answer = self.dialogs.confirm(&mut self.console, &message)?;
+                            *self.ri_mut(Reg::A0)? = answer as i32;
+                        }
+                        SyscallCode::InputDialogInt => {
+                            let message = self.load_message()?;
+                            let answer = self.dialogs.input_int(&mut self.console, &message)?;
+                            let (val, status) = answer.registers();
+                            *self.ri_mut(Reg::A0)? = val;
+                            *self.ri_mut(Reg::A1)? = status;
+                        }
+                        SyscallCode::MessageDialog => {
+                            let message = self.load_message()?;
+                            let kind = self.read(Reg::A1)?.into();
+                            self.dialogs.message(&mut self.console, &message, kind)?;
+                        }
                     }
                 }
+                Funct::Break => return Err(InsnError::Breakpoint(insn.code())),
+                Funct::Jr => return self.jump_to(self.read(insn.rs())?),
+                // Memory is never reordered here, so there's nothing to wait for
+                Funct::Sync => {}
                 Funct::Add => {
-                    let (val, overflow) = self.ri(insn.rs()).overflowing_add(self.ri(insn.rt()));
+                    let (val, overflow) = self
+                        .read_i(insn.rs())?
+                        .overflowing_add(self.read_i(insn.rt())?);
                     *self.ri_mut(insn.rd())? = val;
                     if overflow {
                         return Err(InsnError::IntegerOverflow);
                     }
                 }
                 Funct::AddU => {
-                    let (val, _overflow) = self.ru(insn.rs()).overflowing_add(self.ru(insn.rt()));
-                    *self.ru_mut(insn.rd()) = val;
+                    let (val, _overflow) =
+                        self.read(insn.rs())?.overflowing_add(self.read(insn.rt())?);
+                    *self.ru_mut(insn.rd())? = val;
                 }
                 Funct::Sub => {
-                    let (val, overflow) = self.ri(insn.rs()).overflowing_sub(self.ri(insn.rt()));
+                    let (val, overflow) = self
+                        .read_i(insn.rs())?
+                        .overflowing_sub(self.read_i(insn.rt())?);
                     *self.ri_mut(insn.rd())? = val;
                     if overflow {
                         return Err(InsnError::IntegerOverflow);
                     }
                 }
                 Funct::SubU => {
-                    let (val, _overflow) = self.ru(insn.rs()).overflowing_sub(self.ru(insn.rt()));
-                    *self.ru_mut(insn.rd()) = val;
+                    let (val, _overflow) =
+                        self.read(insn.rs())?.overflowing_sub(self.read(insn.rt())?);
+                    *self.ru_mut(insn.rd())? = val;
                 }
-                Funct::And => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) & self.ru(insn.rt()),
-                Funct::Or => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) | self.ru(insn.rt()),
-                Funct::Xor => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) ^ self.ru(insn.rt()),
-                Funct::Nor => *self.ru_mut(insn.rd())? = !(self.ru(insn.rs()) | self.ru(insn.rt())),
+                Funct::And => {
+                    *self.ru_mut(insn.rd())? = self.read(insn.rs())? & self.read(insn.rt())?
+                }
+                Funct::Or => {
+                    *self.ru_mut(insn.rd())? = self.read(insn.rs())? | self.read(insn.rt())?
+                }
+                Funct::Xor => {
+                    *self.ru_mut(insn.rd())? = self.read(insn.rs())? ^ self.read(insn.rt())?
+                }
+                Funct::Nor => {
+                    *self.ru_mut(insn.rd())? = !(self.read(insn.rs())? | self.read(insn.rt())?)
+                }
+                Funct::MfHi => *self.ru_mut(insn.rd())? = self.hi,
+                Funct::MfLo => *self.ru_mut(insn.rd())? = self.lo,
             },
+            Opcode::RegImm => {
+                let (rs, imm) = (self.read(insn.rs())?, insn.di());
+                let op = insn.regimm()?;
+                let cond = match op {
+                    RegImm::BLtZ => (rs as i32) < 0,
+                    RegImm::BGeZ => rs as i32 >= 0,
+                    RegImm::TGeI => rs as i32 >= imm,
+                    RegImm::TGeIU => rs >= imm as u32,
+                    RegImm::TLtI => (rs as i32) < imm,
+                    RegImm::TLtIU => rs < imm as u32,
+                    RegImm::TEqI => rs as i32 == imm,
+                    RegImm::TNeI => rs as i32 != imm,
+                };
+                match op {
+                    RegImm::BLtZ | RegImm::BGeZ if cond => return self.branch(insn),
+                    RegImm::BLtZ | RegImm::BGeZ => {}
+                    _ if cond => return Err(InsnError::Trap),
+                    _ => {}
+                }
+            }
+            Opcode::J => return self.jump(insn),
+            Opcode::Jal => {
+                // Resolved first so a bad target leaves $ra alone
+                let idx = self.jump_index(jump_target(self.pc_addr(), insn.addr()))?;
+                // No delay slot, so the return address is the next instruction
+                *self.ru_mut(Reg::RA)? = self.pc_for_insn_index(self.pc as i64 + 1);
+                self.pc = idx;
+                return Ok(());
+            }
+            Opcode::BLeZ => {
+                if self.read_i(insn.rs())? <= 0 {
+                    return self.branch(insn);
+                }
+            }
+            Opcode::BGtZ => {
+                if self.read_i(insn.rs())? > 0 {
+                    return self.branch(insn);
+                }
+            }
             Opcode::AddI => {
-                let (val, overflow) = self.ri(insn.rs()).overflowing_add(insn.di());
-                *self.ri_mut(insn.rd())? = val;
+                let (val, overflow) = self.read_i(insn.rs())?.overflowing_add(insn.di());
+                *self.ri_mut(insn.rt())? = val;
                 if overflow {
                     return Err(InsnError::IntegerOverflow);
                 }
             }
+            // Despite the name, the immediate is sign-extended; only the overflow trap differs
             Opcode::AddIU => {
-                let (val, overflow) = self.ru(insn.rs()).overflowing_add(insn.du());
-                *self.ru_mut(insn.rd())? = val;
-                if overflow {
-                    return Err(InsnError::IntegerOverflow);
+                *self.ru_mut(insn.rt())? = self.read(insn.rs())?.wrapping_add(insn.di() as u32)
+            }
+            Opcode::AndI => *self.ru_mut(insn.rt())? = self.read(insn.rs())? & insn.du(),
+            Opcode::OrI => *self.ru_mut(insn.rt())? = self.read(insn.rs())? | insn.du(),
+            Opcode::XorI => *self.ru_mut(insn.rt())? = self.read(insn.rs())? ^ insn.du(),
+            Opcode::LuI => *self.ru_mut(insn.rt())? = insn.du() << 16,
+            Opcode::Special2 => match insn.special2_funct()? {
+                Special2Funct::Mul => {
+                    let product = self.read_i(insn.rs())? as i64 * self.read_i(insn.rt())? as i64;
+                    *self.ri_mut(insn.rd())? = product as i32;
+                    if self.strict_mul && product != product as i32 as i64 {
+                        return Err(InsnError::IntegerOverflow);
+                    }
                 }
+                funct => {
+                    let product = match funct {
+                        Special2Funct::MAdd | Special2Funct::MSub => {
+                            (self.read_i(insn.rs())? as i64 * self.read_i(insn.rt())? as i64) as u64
+                        }
+                        _ => self.read(insn.rs())? as u64 * self.read(insn.rt())? as u64,
+                    };
+                    let acc = (self.hi as u64) << 32 | self.lo as u64;
+                    // Two's complement makes the signed and unsigned sums the same bits
+                    let acc = match funct {
+                        Special2Funct::MAdd | Special2Funct::MAddU => acc.wrapping_add(product),
+                        _ => acc.wrapping_sub(product),
+                    };
+                    (self.hi, self.lo) = ((acc >> 32) as u32, acc as u32);
+                }
+            },
+            Opcode::Special3 => match insn.special3_funct()? {
+                Special3Funct::RdHwr => {
+                    let val = match insn.rd() as u32 {
+                        // CPU number
+                        0 => 0,
+                        // Cycle counter, which ticks once per instruction here
+                        2 => self.executed as u32,
+                        // Cycles per counter tick
+                        3 => 1,
+                        n => return Err(InsnError::InvalidHardwareRegister(n)),
+                    };
+                    *self.ru_mut(insn.rt())? = val;
+                }
+                Special3Funct::Ext => {
+                    let (pos, size) = (insn.shamt(), insn.rd() as u32 + 1);
+                    // Fields running past bit 31 are UNPREDICTABLE; take the bits that exist
+                    let size = size.min(32 - pos);
+                    *self.ru_mut(insn.rt())? = (self.read(insn.rs())? >> pos) & low_bits(size);
+                }
+                Special3Funct::Ins => {
+                    let (msb, lsb) = (insn.rd() as u32, insn.shamt());
+                    let rt = self.read(insn.rt())?;
+                    // msb < lsb is UNPREDICTABLE; treat it as an empty field
+                    let mask = low_bits((msb + 1).saturating_sub(lsb)) << lsb;
+                    let val = (rt & !mask) | (self.read(insn.rs())? << lsb & mask);
+                    *self.ru_mut(insn.rt())? = val;
+                }
+            },
+            Opcode::Cop1 => match insn.cop1_fmt()? {
+                Cop1Fmt::MfC1 => *self.ru_mut(insn.rt())? = self.f_regs[insn.fs()],
+                Cop1Fmt::MtC1 => self.f_regs[insn.fs()] = self.read(insn.rt())?,
+                Cop1Fmt::S => {
+                    let fs = f32::from_bits(self.f_regs[insn.fs()]);
+                    let ft = f32::from_bits(self.f_regs[insn.ft()]);
+                    match insn.cop1_funct()? {
+                        Cop1Funct::Add => self.f_regs[insn.fd()] = (fs + ft).to_bits(),
+                        Cop1Funct::Sub => self.f_regs[insn.fd()] = (fs - ft).to_bits(),
+                        Cop1Funct::Mul => self.f_regs[insn.fd()] = (fs * ft).to_bits(),
+                        Cop1Funct::Div => self.f_regs[insn.fd()] = (fs / ft).to_bits(),
+                        Cop1Funct::CEq => self.fp_cond = fs == ft,
+                        Cop1Funct::CLt => self.fp_cond = fs < ft,
+                        Cop1Funct::CLe => self.fp_cond = fs <= ft,
+                        Cop1Funct::CvtW => {
+                            let rounded = match self.rounding {
+                                RoundingMode::Nearest => fs.round_ties_even(),
+                                RoundingMode::Zero => fs.trunc(),
+                                RoundingMode::Up => fs.ceil(),
+                                RoundingMode::Down => fs.floor(),
+                            };
+                            // NaN and out-of-range values give the invalid-operation result
+                            let word = if (i32::MIN as f32..i32::MAX as f32).contains(&rounded) {
+                                rounded as i32
+                            } else {
+                                i32::MAX
+                            };
+                            self.f_regs[insn.fd()] = word as u32;
+                        }
+                        Cop1Funct::CvtS => return Err(InsnError::InvalidFunct(insn.0 & 0x3F)),
+                    }
+                }
+                Cop1Fmt::W => match insn.cop1_funct()? {
+                    Cop1Funct::CvtS => {
+                        let word = self.f_regs[insn.fs()] as i32;
+                        self.f_regs[insn.fd()] = self.rounding.int_to_f32(word).to_bits();
+                    }
+                    _ => return Err(InsnError::InvalidFunct(insn.0 & 0x3F)),
+                },
+                Cop1Fmt::Bc => {
+                    // Bit 16 selects bc1t over bc1f
+                    if self.fp_cond == ((insn.0 >> 16) & 1 == 1) {
+                        return self.branch(insn);
+                    }
+                }
+            },
+            Opcode::Lw => {
+                let addr = self.effective_addr(insn)?;
+                let val = self.load(addr)?;
+                *self.ru_mut(insn.rt())? = val;
+                self.trace_access(addr, val, false, 4);
+            }
+            Opcode::Sw => {
+                let (addr, val) = (self.effective_addr(insn)?, self.read(insn.rt())?);
+                self.store_word(addr, val)?;
+                self.trace_access(addr, val, true, 4);
+            }
+            Opcode::Ll => {
+                let addr = self.effective_addr(insn)?;
+                let val = self.load(addr)?;
+                *self.ru_mut(insn.rt())? = val;
+                self.link = Some(addr);
+                self.trace_access(addr, val, false, 4);
+            }
+            // Only a store to the linked word can break the link, since nothing else runs between
+            // the `ll` and the `sc`
+            Opcode::Sc => {
+                let (addr, val) = (self.effective_addr(insn)?, self.read(insn.rt())?);
+                let linked = self.link.take() == Some(addr);
+                if linked {
+                    self.store_word(addr, val)?;
+                    self.trace_access(addr, val, true, 4);
+                }
+                *self.ru_mut(insn.rt())? = linked as u32;
             }
-            Opcode::AndI => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) & insn.du(),
-            Opcode::OrI => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) | insn.du(),
-            Opcode::XorI => *self.ru_mut(insn.rd())? = self.ru(insn.rs()) ^ insn.du(),
-            Opcode::LuI => *self.ru_mut(insn.rd())? = insn.du() << 16,
+            Opcode::Lwc1 => {
+                let addr = self.effective_addr(insn)?;
+                self.f_regs[insn.ft()] = self.load(addr)?;
+                self.trace_access(addr, self.f_regs[insn.ft()], false, 4);
+            }
+            Opcode::Swc1 => {
+                let (addr, val) = (self.effective_addr(insn)?, self.f_regs[insn.ft()]);
+                self.store_word(addr, val)?;
+                self.trace_access(addr, val, true, 4);
+            }
+            opcode @ (Opcode::BEq
+            | Opcode::BNe
+            | Opcode::SltI
+            | Opcode::SltIU
+            | Opcode::Cop0
+            | Opcode::Cop2
+            | Opcode::Cop3
+            | Opcode::Lb
+            | Opcode::Lh
+            | Opcode::Lwl
+            | Opcode::Lbu
+            | Opcode::Lhu
+            | Opcode::Lwr
+            | Opcode::Sb
+            | Opcode::Sh
+            | Opcode::Swl
+            | Opcode::Swr
+            | Opcode::Lwc2
+            | Opcode::Lwc3
+            | Opcode::Swc2
+            | Opcode::Swc3) => return Err(InsnError::UnimplementedOpcode(opcode)),
         }
 
         self.pc += 1;
@@ -88,159 +1084,4283 @@ impl Computer {
         Ok(())
     }
 
-    fn ru(&self, reg: Reg) -> u32 {
-        self.regs[reg as usize]
+    /// Jumps to the instruction `offset` words past the one following `insn`. Landing just past the
+    /// last instruction is allowed and ends the program, like falling off the end
+    fn branch(&mut self, insn: Insn) -> Result<(), InsnError> {
+        let target = self.pc as i64 + 1 + insn.di() as i64;
+        if target < 0 || target > self.program.len() as i64 {
+            return Err(InsnError::BranchOutOfText(self.pc_for_insn_index(target)));
+        }
+        self.pc = target as usize;
+        Ok(())
     }
 
-    fn ri(&self, reg: Reg) -> i32 {
-        unsafe { transmute(self.regs[reg as usize]) }
+    /// Jumps to `insn`'s target in the 256MB region of the next instruction
+    fn jump(&mut self, insn: Insn) -> Result<(), InsnError> {
+        self.jump_to(jump_target(self.pc_addr(), insn.addr()))
     }
 
-    fn ru_mut(&mut self, reg: Reg) -> Result<&mut u32, InsnError> {
-        match reg {
-            Reg::Zero => Err(InsnError::RegMutZero),
-            r => Ok(&mut self.regs[r as usize]),
+    fn jump_to(&mut self, target: u32) -> Result<(), InsnError> {
+        self.pc = self.jump_index(target)?;
+        Ok(())
+    }
+
+    /// Index of the instruction a jump to `target` lands on
+    fn jump_index(&self, target: u32) -> Result<usize, InsnError> {
+        self.insn_index_for_pc(target)
+            .ok_or(InsnError::BranchOutOfText(target))
+    }
+
+    fn trace_access(&mut self, addr: u32, value: u32, is_write: bool, size: u32) {
+        let pc = self.pc_addr();
+        if let Some(trace) = &mut self.mem_trace {
+            trace.push(MemAccess {
+                pc,
+                addr,
+                value,
+                is_write,
+                size,
+            });
         }
     }
 
-    fn ri_mut(&mut self, reg: Reg) -> Result<&mut i32, InsnError> {
-        match reg {
-            Reg::Zero => Err(InsnError::RegMutZero),
-            r => unsafe { Ok(transmute(&mut self.regs[r as usize])) },
+    /// `base + offset` for loads and stores
+    fn effective_addr(&self, insn: Insn) -> Result<u32, InsnError> {
+        Ok(self.read(insn.rs())?.wrapping_add(insn.di() as u32))
+    }
+
+    /// Load on behalf of an instruction, which fails on never-written words in strict memory mode
+    fn load(&self, addr: u32) -> Result<u32, InsnError> {
+        let val = self.load_word(addr)?;
+        if self.strict_memory && self.text_index(addr).is_none() && !self.mem.contains_key(&addr) {
+            return Err(InsnError::UninitializedMemoryRead(addr));
         }
+        Ok(val)
     }
-}
 
-#[derive(Debug, Copy, Clone)]
-pub struct Insn(u32);
+    pub fn load_word(&self, addr: u32) -> Result<u32, InsnError> {
+        if !addr.is_multiple_of(4) {
+            return Err(InsnError::UnalignedAccess(addr));
+        }
+        Ok(self.word_at(addr))
+    }
 
-impl Insn {
-    fn opcode(&self) -> Result<Opcode, InsnError> {
-        Opcode::try_from_primitive(self.0 >> 26).map_err(|e| InsnError::InvalidOpcode(e.number))
+    fn store_word(&mut self, addr: u32, val: u32) -> Result<(), InsnError> {
+        if !addr.is_multiple_of(4) {
+            return Err(InsnError::UnalignedAccess(addr));
+        }
+        self.set_word(addr, val)
     }
 
-    fn rs(&self) -> Reg {
-        unsafe { Reg::unchecked_transmute_from((self.0 >> 21) & 0x1F) }
+    /// Index into `program` of the word-aligned `addr`, if it's in the text segment
+    fn text_index(&self, addr: u32) -> Option<usize> {
+        insn_index(self.text_base, addr).filter(|&idx| idx < self.program.len())
     }
 
-    fn rt(&self) -> Reg {
-        unsafe { Reg::unchecked_transmute_from((self.0 >> 16) & 0x1F) }
+    /// The word at the word-aligned `addr`. Text addresses read the program
+    fn word_at(&self, addr: u32) -> u32 {
+        match self.text_index(addr) {
+            Some(idx) => self.program[idx].0,
+            None => self.mem.get(&addr).copied().unwrap_or(0),
+        }
     }
 
-    fn rd(&self) -> Reg {
-        unsafe { Reg::unchecked_transmute_from((self.0 >> 11) & 0x1F) }
+    /// Writes to the text segment patch the program, if self-modifying code is allowed. Breaks an
+    /// `ll` link to `addr`
+    fn set_word(&mut self, addr: u32, val: u32) -> Result<(), InsnError> {
+        if self.link == Some(addr) {
+            self.link = None;
+        }
+        match self.text_index(addr) {
+            Some(_) if !self.self_modifying => return Err(InsnError::WriteToTextSegment(addr)),
+            Some(idx) => {
+                let loaded = std::mem::replace(&mut self.program[idx], Insn(val));
+                self.overwritten.entry(addr).or_insert(loaded);
+            }
+            None => {
+                self.mem.insert(addr, val);
+            }
+        }
+        Ok(())
     }
 
-    fn shamt(&self) -> u32 {
-        (self.0 >> 6) & 0x1F
+    /// Where the byte at `addr` sits within its word
+    fn byte_shift(&self, addr: u32) -> u32 {
+        match self.endianness {
+            Endianness::Big => (3 - (addr & 3)) * 8,
+            Endianness::Little => (addr & 3) * 8,
+        }
     }
 
-    fn funct(&self) -> Result<Funct, InsnError> {
-        Funct::try_from_primitive(self.0 & 0x3F).map_err(|e| InsnError::InvalidFunct(e.number))
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn load_byte(&self, addr: u32) -> u8 {
+        (self.word_at(addr & !3) >> self.byte_shift(addr)) as u8
     }
 
-    fn du(&self) -> u32 {
-        self.0 & 0xFFFF
+    /// The halfword at the 2-aligned `addr`, assembled per the endianness
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn load_half(&self, addr: u32) -> Result<u16, InsnError> {
+        if !addr.is_multiple_of(2) {
+            return Err(InsnError::UnalignedAccess(addr));
+        }
+        let (first, second) = (self.load_byte(addr), self.load_byte(addr.wrapping_add(1)));
+        Ok(match self.endianness {
+            Endianness::Big => u16::from_be_bytes([first, second]),
+            Endianness::Little => u16::from_le_bytes([first, second]),
+        })
     }
 
-    fn di(&self) -> i32 {
-        unsafe { transmute((self.0 & 0xFFFF) | 0xFFFF0000) }
+    /// The null-terminated string at `addr`, without the null. Reads a word at a time, since each
+    /// word is a hash lookup and strings can be kilobytes long
+    fn load_string(&self, addr: u32) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut word_addr = addr & !3;
+        let mut first = addr & 3;
+        loop {
+            let word = self.word_at(word_addr);
+            for offset in first..4 {
+                let b = (word >> self.byte_shift(offset)) as u8;
+                if b == 0 {
+                    return bytes;
+                }
+                bytes.push(b);
+            }
+            word_addr = word_addr.wrapping_add(4);
+            first = 0;
+        }
     }
 
-    fn addr(&self) -> u32 {
-        self.0 & 0x3FFFFFF
+    /// The string `$a0` points to, for the dialog syscalls
+    fn load_message(&self) -> Result<String, InsnError> {
+        let bytes = self.load_string(self.read(Reg::A0)?);
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
     }
-}
 
-#[derive(Copy, num_enum::TryFromPrimitive)]
-#[repr(u32)]
-enum Opcode {
-    Reg = 0b000000,
-    AddI = 0b001000,
-    AddIU = 0b001001,
-    AndI = 0b001100,
-    OrI = 0b001101,
-    XorI = 0b001110,
-    LuI = 0b001111,
-}
+    fn store_byte(&mut self, addr: u32, val: u8) -> Result<(), InsnError> {
+        let shift = self.byte_shift(addr);
+        let word = self.word_at(addr & !3);
+        self.set_word(addr & !3, (word & !(0xFF << shift)) | (val as u32) << shift)
+    }
 
-#[derive(Copy, num_enum::UnsafeFromPrimitive)]
-#[repr(u32)]
-enum Reg {
-    Zero = 0,
-    At = 1,
-    V0 = 2,
-    V1 = 3,
-    A0 = 4,
-    A1 = 5,
-    A2 = 6,
-    A3 = 7,
-    T0 = 8,
-    T1 = 9,
-    T2 = 10,
-    T3 = 11,
-    T4 = 12,
-    T5 = 13,
-    T6 = 14,
-    T7 = 15,
-    S0 = 16,
-    S1 = 17,
-    S2 = 18,
-    S3 = 19,
-    S4 = 20,
-    S5 = 21,
-    S6 = 22,
-    S7 = 23,
-    T8 = 24,
-    T9 = 25,
-    K0 = 26,
-    K1 = 27,
-    GP = 28,
-    SP = 29,
-    FP = 30,
-    RA = 31,
+    /// Reads lines until one is a number or `bad_int_policy` gives up on them
+    fn read_int(&mut self) -> Result<i32, InsnError> {
+        let mut retries = match self.bad_int_policy {
+            BadIntPolicy::Zero => None,
+            BadIntPolicy::Retry(n) => Some(n),
+        };
+        loop {
+            let line = self.console.read_line()?;
+            if line.is_empty() {
+                return self.eof_sentinel(-1);
+            }
+            match (line.trim().parse(), retries) {
+                (Ok(val), _) => return Ok(val),
+                // Like SPIM, anything that isn't a number reads as 0
+                (Err(_), None) => return Ok(0),
+                (Err(_), Some(0)) => return Err(InsnError::BadIntInput(line.trim().to_string())),
+                (Err(_), Some(n)) => retries = Some(n - 1),
+            }
+        }
+    }
+
+    /// `sentinel` under `EofPolicy::Sentinel`, otherwise the error
+    fn eof_sentinel(&self, sentinel: i32) -> Result<i32, InsnError> {
+        match self.eof_policy {
+            EofPolicy::Sentinel => Ok(sentinel),
+            EofPolicy::Error => Err(InsnError::UnexpectedEof),
+        }
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mem(&self) -> &HashMap<u32, u32> {
+        &self.mem
+    }
+
+    /// Writes `bytes`, e.g. an assembled `.data` section, to memory starting at the data base. Fails
+    /// without writing anything if they would overlap the text segment
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn load_data(&mut self, bytes: &[u8]) -> Result<(), InsnError> {
+        let mut addrs = (0..bytes.len() as u32).map(|n| self.data_base.wrapping_add(n));
+        if let Some(addr) = addrs.find(|&addr| self.text_index(addr & !3).is_some()) {
+            return Err(InsnError::WriteToTextSegment(addr));
+        }
+        self.data = bytes.to_vec();
+        self.lay_out_data();
+        Ok(())
+    }
+
+    /// Writes the `load_data` image at the data base
+    fn lay_out_data(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        for (n, &b) in data.iter().enumerate() {
+            self.store_byte(self.data_base.wrapping_add(n as u32), b)
+                .expect("data segment overlaps the text segment");
+        }
+        self.data = data;
+    }
+
+    /// Replaces all of memory with `mem`, e.g. a snapshot from `mem`. Every key must be
+    /// word-aligned; on error memory is left unchanged. The text segment still reads the program
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn load_memory(&mut self, mem: HashMap<u32, u32>) -> Result<(), InsnError> {
+        if let Some(&addr) = mem.keys().find(|addr| !addr.is_multiple_of(4)) {
+            return Err(InsnError::UnalignedAccess(addr));
+        }
+        self.mem = mem;
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn hi(&self) -> u32 {
+        self.hi
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn lo(&self) -> u32 {
+        self.lo
+    }
+
+    /// Raw bits of `$f{idx}`
+    pub fn fpr(&self, idx: usize) -> Result<u32, InsnError> {
+        Ok(self.f_regs[Self::fpr_index(idx)?])
+    }
+
+    pub fn fpr_f32(&self, idx: usize) -> Result<f32, InsnError> {
+        self.fpr(idx).map(f32::from_bits)
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_fpr_f32(&mut self, idx: usize, val: f32) -> Result<(), InsnError> {
+        self.f_regs[Self::fpr_index(idx)?] = val.to_bits();
+        Ok(())
+    }
+
+    /// The double in the pair `$f{idx}`/`$f{idx + 1}`, with the high word in the odd register.
+    /// `idx` must be even
+    pub fn fpr_f64(&self, idx: usize) -> Result<f64, InsnError> {
+        let idx = Self::even_fpr(idx)?;
+        let bits = (self.f_regs[idx + 1] as u64) << 32 | self.f_regs[idx] as u64;
+        Ok(f64::from_bits(bits))
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_fpr_f64(&mut self, idx: usize, val: f64) -> Result<(), InsnError> {
+        let idx = Self::even_fpr(idx)?;
+        let bits = val.to_bits();
+        self.f_regs[idx] = bits as u32;
+        self.f_regs[idx + 1] = (bits >> 32) as u32;
+        Ok(())
+    }
+
+    fn fpr_index(idx: usize) -> Result<usize, InsnError> {
+        if idx < 32 {
+            Ok(idx)
+        } else {
+            Err(InsnError::NoSuchFpRegister(idx))
+        }
+    }
+
+    fn even_fpr(idx: usize) -> Result<usize, InsnError> {
+        if Self::fpr_index(idx)?.is_multiple_of(2) {
+            Ok(idx)
+        } else {
+            Err(InsnError::OddFpRegister(idx))
+        }
+    }
+
+    /// Every GPR paired with its value, in encoding order
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn registers(&self) -> [(Reg, u32); 32] {
+        let mut regs = [(Reg::Zero, 0); 32];
+        for (slot, reg) in regs.iter_mut().zip(Reg::all()) {
+            *slot = (reg, self.ru(reg));
+        }
+        regs
+    }
+
+    /// The registers as MARS's command-line mode prints them after a run: one `name<TAB>value` line
+    /// per GPR in encoding order, then `pc`, `hi` and `lo`. Hex values are `0x` and eight digits;
+    /// decimal ones are signed, as with MARS's `dec` option
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn dump_registers_mars(&self, radix: Radix) -> String {
+        let gprs = self.registers().map(|(reg, val)| (reg.to_string(), val));
+        let special = [("pc", self.pc_addr()), ("hi", self.hi), ("lo", self.lo)]
+            .map(|(name, val)| (name.to_string(), val));
+        let mut out = String::new();
+        for (name, val) in gprs.into_iter().chain(special) {
+            let val = match radix {
+                Radix::Hex => format!("{val:#010x}"),
+                Radix::Decimal => (val as i32).to_string(),
+            };
+            out += &format!("{name}\t{val}\n");
+        }
+        out
+    }
+
+    pub fn ru(&self, reg: Reg) -> u32 {
+        self.regs[reg as usize]
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn ri(&self, reg: Reg) -> i32 {
+        self.regs[reg as usize] as i32
+    }
+
+    /// Writes `val` to `reg` as if an instruction had, so it counts as initialized. `$zero` is
+    /// always rejected, whatever the zero-write policy
+    pub fn set_register(&mut self, reg: Reg, val: u32) -> Result<(), InsnError> {
+        if reg == Reg::Zero {
+            return Err(InsnError::RegMutZero);
+        }
+        *self.ru_mut(reg)? = val;
+        Ok(())
+    }
+
+    /// Register read on behalf of an instruction, which fails on poisoned registers
+    fn read(&self, reg: Reg) -> Result<u32, InsnError> {
+        if self.poison_regs && self.written & (1 << reg as u32) == 0 {
+            return Err(InsnError::UninitializedRegister(reg));
+        }
+        Ok(self.ru(reg))
+    }
+
+    fn read_i(&self, reg: Reg) -> Result<i32, InsnError> {
+        Ok(self.read(reg)? as i32)
+    }
+
+    /// Writes to `$zero` go through to `regs[0]`, which `exec_next` clears after every instruction,
+    /// unless `strict_zero` makes them an error
+    fn ru_mut(&mut self, reg: Reg) -> Result<&mut u32, InsnError> {
+        match reg {
+            Reg::Zero if self.strict_zero => Err(InsnError::RegMutZero),
+            r => {
+                self.written |= 1 << r as u32;
+                Ok(&mut self.regs[r as usize])
+            }
+        }
+    }
+
+    fn ri_mut(&mut self, reg: Reg) -> Result<&mut i32, InsnError> {
+        let r = self.ru_mut(reg)?;
+        unsafe { Ok(transmute::<&mut u32, &mut i32>(r)) }
+    }
+}
+
+/// Follows MARS, which prints with Java's `toString`: `NaN`, `Infinity` and `-Infinity` for the
+/// special values, and finite values in shortest round-trip form with at least one decimal (`2.0`).
+/// Unlike Java, large and small magnitudes use Rust's exponent style (`1e10` rather than `1.0E10`)
+fn format_float<F: Into<f64> + fmt::Debug + Copy>(val: F) -> String {
+    let wide: f64 = val.into();
+    if wide.is_nan() {
+        "NaN".to_string()
+    } else if wide.is_infinite() {
+        if wide > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{val:?}")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Insn(pub u32);
+
+/// A mask of the low `n` bits, for `n` up to 32
+fn low_bits(n: u32) -> u32 {
+    ((1u64 << n) - 1) as u32
+}
+
+/// Address of instruction `idx` of a program loaded at `text_base`. Wraps around the 32-bit
+/// address space like the hardware
+fn insn_addr(text_base: u32, idx: i64) -> u32 {
+    text_base.wrapping_add((idx as u32).wrapping_mul(4))
+}
+
+/// Index of the instruction at `addr` in a program loaded at `text_base`, if `addr` is
+/// word-aligned, without checking it against the program's length. The inverse of `insn_addr`;
+/// every address becomes an index through here, so `jal`'s link, `jr`'s target and stores to the
+/// text segment agree
+fn insn_index(text_base: u32, addr: u32) -> Option<usize> {
+    let offset = addr.wrapping_sub(text_base);
+    offset.is_multiple_of(4).then_some((offset / 4) as usize)
+}
+
+/// Address a `j`/`jal` at `pc` with the 26-bit `index` goes to
+fn jump_target(pc: u32, index: u32) -> u32 {
+    (pc.wrapping_add(4) & 0xF000_0000) | index << 2
+}
+
+impl Insn {
+    /// Packs an R-type (SPECIAL) instruction
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn reg(funct: Funct, rd: Reg, rs: Reg, rt: Reg, shamt: u32) -> Insn {
+        Insn(
+            (rs as u32) << 21
+                | (rt as u32) << 16
+                | (rd as u32) << 11
+                | (shamt & 0x1F) << 6
+                | funct as u32,
+        )
+    }
+
+    /// Packs an I-type instruction. Signed immediates are passed as their 16-bit two's complement
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn imm(opcode: Opcode, rt: Reg, rs: Reg, imm: u16) -> Insn {
+        Insn((opcode as u32) << 26 | (rs as u32) << 21 | (rt as u32) << 16 | imm as u32)
+    }
+
+    /// Packs a J-type instruction jumping to `addr`. Only bits 2-27 are encoded; the rest come
+    /// from the jump's own address when it runs
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn jump(opcode: Opcode, addr: u32) -> Insn {
+        Insn((opcode as u32) << 26 | (addr >> 2) & 0x3FF_FFFF)
+    }
+
+    pub fn opcode(&self) -> Result<Opcode, InsnError> {
+        Opcode::try_from_primitive(self.0 >> 26).map_err(|e| InsnError::InvalidOpcode(e.number))
+    }
+
+    pub fn rs(&self) -> Reg {
+        unsafe { Reg::unchecked_transmute_from((self.0 >> 21) & 0x1F) }
+    }
+
+    pub fn rt(&self) -> Reg {
+        unsafe { Reg::unchecked_transmute_from((self.0 >> 16) & 0x1F) }
+    }
+
+    pub fn rd(&self) -> Reg {
+        unsafe { Reg::unchecked_transmute_from((self.0 >> 11) & 0x1F) }
+    }
+
+    pub fn shamt(&self) -> u32 {
+        (self.0 >> 6) & 0x1F
+    }
+
+    pub fn funct(&self) -> Result<Funct, InsnError> {
+        Funct::try_from_primitive(self.0 & 0x3F).map_err(|e| InsnError::InvalidFunct(e.number))
+    }
+
+    /// 20-bit code field of `syscall` and `break`
+    pub fn code(&self) -> u32 {
+        (self.0 >> 6) & 0xFFFFF
+    }
+
+    pub fn du(&self) -> u32 {
+        self.0 & 0xFFFF
+    }
+
+    /// Sign-extended immediate
+    pub fn di(&self) -> i32 {
+        self.0 as u16 as i16 as i32
+    }
+
+    pub fn addr(&self) -> u32 {
+        self.0 & 0x3FFFFFF
+    }
+
+    /// The `rt` field of a REGIMM instruction
+    pub fn regimm(&self) -> Result<RegImm, InsnError> {
+        RegImm::try_from_primitive((self.0 >> 16) & 0x1F)
+            .map_err(|e| InsnError::InvalidRegImm(e.number))
+    }
+
+    pub fn cop1_fmt(&self) -> Result<Cop1Fmt, InsnError> {
+        Cop1Fmt::try_from_primitive((self.0 >> 21) & 0x1F)
+            .map_err(|e| InsnError::InvalidFmt(e.number))
+    }
+
+    pub fn special2_funct(&self) -> Result<Special2Funct, InsnError> {
+        Special2Funct::try_from_primitive(self.0 & 0x3F)
+            .map_err(|e| InsnError::InvalidFunct(e.number))
+    }
+
+    pub fn special3_funct(&self) -> Result<Special3Funct, InsnError> {
+        Special3Funct::try_from_primitive(self.0 & 0x3F)
+            .map_err(|e| InsnError::InvalidFunct(e.number))
+    }
+
+    pub fn cop1_funct(&self) -> Result<Cop1Funct, InsnError> {
+        Cop1Funct::try_from_primitive(self.0 & 0x3F).map_err(|e| InsnError::InvalidFunct(e.number))
+    }
+
+    /// FP register index in the `rt` position
+    pub fn ft(&self) -> usize {
+        ((self.0 >> 16) & 0x1F) as usize
+    }
+
+    /// FP register index in the `rd` position
+    pub fn fs(&self) -> usize {
+        ((self.0 >> 11) & 0x1F) as usize
+    }
+
+    /// FP register index in the `shamt` position
+    pub fn fd(&self) -> usize {
+        ((self.0 >> 6) & 0x1F) as usize
+    }
+}
+
+/// Structured form of an instruction word. Unlike `Insn::opcode` and `Insn::funct`, decoding never
+/// fails: words that don't name a supported instruction become `Unknown`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub enum DecodedInsn {
+    Reg {
+        funct: Funct,
+        rs: Reg,
+        rt: Reg,
+        rd: Reg,
+        shamt: u32,
+    },
+    Imm {
+        opcode: Opcode,
+        rs: Reg,
+        rt: Reg,
+        imm: u32,
+    },
+    RegImm {
+        op: RegImm,
+        rs: Reg,
+        imm: u32,
+    },
+    /// `j`/`jal`. `index` is the 26-bit field: the target's word address within its 256MB
+    /// region
+    Jump {
+        opcode: Opcode,
+        index: u32,
+    },
+    /// MIPS32r2 `rotr`, encoded as `srl` with bit 21 set
+    RotR {
+        rd: Reg,
+        rt: Reg,
+        shamt: u32,
+    },
+    /// MIPS32r2 `rotrv`, encoded as `srlv` with bit 6 set
+    RotRV {
+        rd: Reg,
+        rt: Reg,
+        rs: Reg,
+    },
+    Special2 {
+        funct: Special2Funct,
+        rs: Reg,
+        rt: Reg,
+        rd: Reg,
+    },
+    /// `rdhwr`, whose `rd` field is a hardware register number rather than a GPR
+    Special3 {
+        funct: Special3Funct,
+        rt: Reg,
+        rd: u32,
+    },
+    /// `ext`/`ins`, with the raw `rd` and `shamt` fields. `lsb` is the field's lowest bit; `msb`
+    /// is its size minus 1 for `ext` and its highest bit for `ins`
+    BitField {
+        funct: Special3Funct,
+        rs: Reg,
+        rt: Reg,
+        msb: u32,
+        lsb: u32,
+    },
+    /// Register fields are left as raw indices since their meaning depends on `fmt`. Never has
+    /// `fmt` `Bc`; those decode as `Bc1`
+    Cop1 {
+        fmt: Cop1Fmt,
+        ft: usize,
+        fs: usize,
+        fd: usize,
+        /// Only arithmetic formats have a funct; moves and branches don't
+        funct: Option<Cop1Funct>,
+    },
+    /// `bc1t` when `if_true`, otherwise `bc1f`
+    Bc1 {
+        if_true: bool,
+        imm: u32,
+    },
+    /// `lwc1`/`swc1`, whose `rt` field names an FP register
+    FpMem {
+        opcode: Opcode,
+        base: Reg,
+        ft: usize,
+        imm: u32,
+    },
+    Unknown(u32),
+}
+
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub fn decode(word: u32) -> DecodedInsn {
+    let insn = Insn(word);
+    match insn.opcode() {
+        Ok(Opcode::Reg) => match insn.funct() {
+            Ok(Funct::Srl) if word & (1 << 21) != 0 => DecodedInsn::RotR {
+                rd: insn.rd(),
+                rt: insn.rt(),
+                shamt: insn.shamt(),
+            },
+            Ok(Funct::SrlV) if word & (1 << 6) != 0 => DecodedInsn::RotRV {
+                rd: insn.rd(),
+                rt: insn.rt(),
+                rs: insn.rs(),
+            },
+            Ok(funct) => DecodedInsn::Reg {
+                funct,
+                rs: insn.rs(),
+                rt: insn.rt(),
+                rd: insn.rd(),
+                shamt: insn.shamt(),
+            },
+            Err(_) => DecodedInsn::Unknown(word),
+        },
+        Ok(Opcode::Special2) => match insn.special2_funct() {
+            Ok(funct) => DecodedInsn::Special2 {
+                funct,
+                rs: insn.rs(),
+                rt: insn.rt(),
+                rd: insn.rd(),
+            },
+            Err(_) => DecodedInsn::Unknown(word),
+        },
+        Ok(Opcode::Special3) => match insn.special3_funct() {
+            Ok(funct @ (Special3Funct::Ext | Special3Funct::Ins)) => DecodedInsn::BitField {
+                funct,
+                rs: insn.rs(),
+                rt: insn.rt(),
+                msb: insn.rd() as u32,
+                lsb: insn.shamt(),
+            },
+            Ok(funct) => DecodedInsn::Special3 {
+                funct,
+                rt: insn.rt(),
+                rd: insn.rd() as u32,
+            },
+            Err(_) => DecodedInsn::Unknown(word),
+        },
+        Ok(Opcode::Cop1) => {
+            let Ok(fmt) = insn.cop1_fmt() else {
+                return DecodedInsn::Unknown(word);
+            };
+            let funct = match fmt {
+                Cop1Fmt::Bc => {
+                    return DecodedInsn::Bc1 {
+                        if_true: (word >> 16) & 1 == 1,
+                        imm: insn.du(),
+                    }
+                }
+                Cop1Fmt::MfC1 | Cop1Fmt::MtC1 => None,
+                Cop1Fmt::S => match insn.cop1_funct() {
+                    Ok(funct) if funct != Cop1Funct::CvtS => Some(funct),
+                    _ => return DecodedInsn::Unknown(word),
+                },
+                Cop1Fmt::W => match insn.cop1_funct() {
+                    Ok(Cop1Funct::CvtS) => Some(Cop1Funct::CvtS),
+                    _ => return DecodedInsn::Unknown(word),
+                },
+            };
+            DecodedInsn::Cop1 {
+                fmt,
+                ft: insn.ft(),
+                fs: insn.fs(),
+                fd: insn.fd(),
+                funct,
+            }
+        }
+        Ok(Opcode::RegImm) => match insn.regimm() {
+            Ok(op) => DecodedInsn::RegImm {
+                op,
+                rs: insn.rs(),
+                imm: insn.du(),
+            },
+            Err(_) => DecodedInsn::Unknown(word),
+        },
+        Ok(opcode) if !opcode.implemented() => DecodedInsn::Unknown(word),
+        Ok(opcode @ (Opcode::J | Opcode::Jal)) => DecodedInsn::Jump {
+            opcode,
+            index: insn.addr(),
+        },
+        Ok(opcode @ (Opcode::Lwc1 | Opcode::Swc1)) => DecodedInsn::FpMem {
+            opcode,
+            base: insn.rs(),
+            ft: insn.ft(),
+            imm: insn.du(),
+        },
+        Ok(opcode) => DecodedInsn::Imm {
+            opcode,
+            rs: insn.rs(),
+            rt: insn.rt(),
+            imm: insn.du(),
+        },
+        Err(_) => DecodedInsn::Unknown(word),
+    }
+}
+
+/// A syscall `scanned_syscalls` found
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub enum ScannedSyscall {
+    /// `$v0` is known to hold this code whenever the syscall runs
+    Code(u32),
+    /// `$v0` couldn't be determined statically
+    Unknown,
+}
+
+/// The syscalls `program` can make, found without running it. `$v0` is tracked through
+/// straight-line constant loads (`li`'s `addiu`/`ori`/`lui` forms); anything else that writes it,
+/// a syscall, a branch or jump landing in between, or a return from a `jal` makes it unknown.
+/// Jump targets assume the program is loaded at `TEXT_BASE`
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub fn scanned_syscalls(program: &[Insn]) -> HashSet<ScannedSyscall> {
+    let mut targets = HashSet::new();
+    for (idx, insn) in program.iter().enumerate() {
+        match decode(insn.0) {
+            DecodedInsn::Jump { opcode, index } => {
+                let target = jump_target(insn_addr(TEXT_BASE, idx as i64), index);
+                targets.extend(insn_index(TEXT_BASE, target).map(|target| target as i64));
+                // The callee's `jr $ra` lands after the `jal`, with whatever it left in $v0
+                if opcode == Opcode::Jal {
+                    targets.insert(idx as i64 + 1);
+                }
+            }
+            decoded => {
+                if let Some(offset) = decoded.branch_offset() {
+                    targets.insert(idx as i64 + 1 + offset as i64);
+                }
+            }
+        }
+    }
+    let mut found = HashSet::new();
+    let mut v0 = None;
+    for (idx, insn) in program.iter().enumerate() {
+        if targets.contains(&(idx as i64)) {
+            v0 = None;
+        }
+        let decoded = decode(insn.0);
+        if let DecodedInsn::Reg {
+            funct: Funct::Syscall,
+            ..
+        } = decoded
+        {
+            found.insert(v0.map_or(ScannedSyscall::Unknown, ScannedSyscall::Code));
+            // Some syscalls return in $v0
+            v0 = None;
+        } else if decoded.destination() == Some(Reg::V0) {
+            v0 = match decoded {
+                DecodedInsn::Imm {
+                    opcode, rs, imm, ..
+                } => match (opcode, rs, v0) {
+                    (Opcode::AddI | Opcode::AddIU, Reg::Zero, _) => Some(imm as u16 as i16 as u32),
+                    (Opcode::AddIU, Reg::V0, Some(val)) => {
+                        Some(val.wrapping_add(imm as u16 as i16 as u32))
+                    }
+                    (Opcode::OrI, Reg::Zero, _) => Some(imm),
+                    (Opcode::OrI, Reg::V0, Some(val)) => Some(val | imm),
+                    (Opcode::LuI, _, _) => Some(imm << 16),
+                    _ => None,
+                },
+                _ => None,
+            };
+        }
+    }
+    found
+}
+
+impl DecodedInsn {
+    /// Word offset from the next instruction, for branches
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn branch_offset(&self) -> Option<i32> {
+        match *self {
+            DecodedInsn::RegImm {
+                op: RegImm::BLtZ | RegImm::BGeZ,
+                imm,
+                ..
+            }
+            | DecodedInsn::Imm {
+                opcode: Opcode::BLeZ | Opcode::BGtZ,
+                imm,
+                ..
+            }
+            | DecodedInsn::Bc1 { imm, .. } => Some(imm as u16 as i16 as i32),
+            _ => None,
+        }
+    }
+
+    /// The GPR the instruction writes, if any
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn destination(&self) -> Option<Reg> {
+        match *self {
+            DecodedInsn::Reg {
+                funct: Funct::Syscall | Funct::Break | Funct::Jr | Funct::Sync,
+                ..
+            } => None,
+            DecodedInsn::Reg { rd, .. }
+            | DecodedInsn::RotR { rd, .. }
+            | DecodedInsn::RotRV { rd, .. }
+            | DecodedInsn::Special2 {
+                funct: Special2Funct::Mul,
+                rd,
+                ..
+            } => Some(rd),
+            DecodedInsn::Imm {
+                opcode:
+                    Opcode::AddI
+                    | Opcode::AddIU
+                    | Opcode::AndI
+                    | Opcode::OrI
+                    | Opcode::XorI
+                    | Opcode::LuI
+                    | Opcode::Lw
+                    | Opcode::Ll
+                    | Opcode::Sc,
+                rt,
+                ..
+            }
+            | DecodedInsn::Special3 { rt, .. }
+            | DecodedInsn::BitField { rt, .. } => Some(rt),
+            DecodedInsn::Cop1 {
+                fmt: Cop1Fmt::MfC1,
+                ft,
+                ..
+            } => Some(Reg::from_field(ft as u32)),
+            _ => None,
+        }
+    }
+}
+
+/// Assembly in SPIM's syntax, e.g. `addi $t0, $t0, 1`. Branch offsets are printed in words
+/// relative to the next instruction, since the target address depends on where the word sits
+impl fmt::Display for DecodedInsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            // sll $zero, $zero, 0
+            DecodedInsn::Reg {
+                funct: Funct::Sll,
+                rs: Reg::Zero,
+                rt: Reg::Zero,
+                rd: Reg::Zero,
+                shamt: 0,
+            } => write!(f, "nop"),
+            DecodedInsn::Reg {
+                funct,
+                rs,
+                rt,
+                rd,
+                shamt,
+            } => {
+                let name = funct.mnemonic();
+                match funct {
+                    Funct::Sll | Funct::Srl | Funct::Sra => {
+                        write!(f, "{name} {rd}, {rt}, {shamt}")
+                    }
+                    Funct::SllV | Funct::SrlV | Funct::SraV => write!(f, "{name} {rd}, {rt}, {rs}"),
+                    Funct::Syscall | Funct::Sync => write!(f, "{name}"),
+                    Funct::MfHi | Funct::MfLo => write!(f, "{name} {rd}"),
+                    Funct::Jr => write!(f, "{name} {rs}"),
+                    Funct::Break => {
+                        let code = (rs as u32) << 15 | (rt as u32) << 10 | (rd as u32) << 5 | shamt;
+                        match code {
+                            0 => write!(f, "{name}"),
+                            _ => write!(f, "{name} {code:#x}"),
+                        }
+                    }
+                    _ => write!(f, "{name} {rd}, {rs}, {rt}"),
+                }
+            }
+            DecodedInsn::Imm {
+                opcode,
+                rs,
+                rt,
+                imm,
+            } => {
+                let (name, simm) = (opcode.mnemonic(), imm as u16 as i16);
+                match opcode {
+                    Opcode::BLeZ | Opcode::BGtZ => write!(f, "{name} {rs}, {simm}"),
+                    Opcode::LuI => write!(f, "{name} {rt}, {imm:#x}"),
+                    Opcode::Lw | Opcode::Sw | Opcode::Ll | Opcode::Sc => {
+                        write!(f, "{name} {rt}, {simm}({rs})")
+                    }
+                    Opcode::AndI | Opcode::OrI | Opcode::XorI => {
+                        write!(f, "{name} {rt}, {rs}, {imm:#x}")
+                    }
+                    _ => write!(f, "{name} {rt}, {rs}, {simm}"),
+                }
+            }
+            DecodedInsn::RegImm { op, rs, imm } => {
+                write!(f, "{} {rs}, {}", op.mnemonic(), imm as u16 as i16)
+            }
+            // Without the jump's own address only the low 28 bits of the target are known
+            DecodedInsn::Jump { opcode, index } => {
+                write!(f, "{} {:#010x}", opcode.mnemonic(), index << 2)
+            }
+            DecodedInsn::RotR { rd, rt, shamt } => write!(f, "rotr {rd}, {rt}, {shamt}"),
+            DecodedInsn::RotRV { rd, rt, rs } => write!(f, "rotrv {rd}, {rt}, {rs}"),
+            DecodedInsn::Special2 {
+                funct: Special2Funct::Mul,
+                rs,
+                rt,
+                rd,
+            } => write!(f, "mul {rd}, {rs}, {rt}"),
+            DecodedInsn::Special2 { funct, rs, rt, .. } => {
+                write!(f, "{} {rs}, {rt}", funct.mnemonic())
+            }
+            DecodedInsn::Special3 { funct, rt, rd } => {
+                write!(f, "{} {rt}, ${rd}", funct.mnemonic())
+            }
+            // `ext rt, rs, pos, size`
+            DecodedInsn::BitField {
+                funct,
+                rs,
+                rt,
+                msb,
+                lsb,
+            } => {
+                let size = match funct {
+                    Special3Funct::Ext => msb as i32 + 1,
+                    _ => msb as i32 + 1 - lsb as i32,
+                };
+                write!(f, "{} {rt}, {rs}, {lsb}, {size}", funct.mnemonic())
+            }
+            DecodedInsn::Cop1 {
+                fmt,
+                ft,
+                fs,
+                fd,
+                funct,
+            } => match (fmt, funct) {
+                (Cop1Fmt::MfC1, _) => write!(f, "mfc1 {}, $f{fs}", Reg::from_field(ft as u32)),
+                (Cop1Fmt::MtC1, _) => write!(f, "mtc1 {}, $f{fs}", Reg::from_field(ft as u32)),
+                (_, None) => write!(f, "cop1 {fmt:?}"),
+                (_, Some(funct)) => {
+                    let name = funct.mnemonic();
+                    let suffix = if fmt == Cop1Fmt::W { "w" } else { "s" };
+                    match funct {
+                        Cop1Funct::CEq | Cop1Funct::CLt | Cop1Funct::CLe => {
+                            write!(f, "{name}.{suffix} $f{fs}, $f{ft}")
+                        }
+                        Cop1Funct::CvtS | Cop1Funct::CvtW => {
+                            write!(f, "{name}.{suffix} $f{fd}, $f{fs}")
+                        }
+                        _ => write!(f, "{name}.{suffix} $f{fd}, $f{fs}, $f{ft}"),
+                    }
+                }
+            },
+            DecodedInsn::Bc1 { if_true, imm } => {
+                let tf = if if_true { 't' } else { 'f' };
+                write!(f, "bc1{tf} {}", imm as u16 as i16)
+            }
+            DecodedInsn::FpMem {
+                opcode,
+                base,
+                ft,
+                imm,
+            } => write!(
+                f,
+                "{} $f{ft}, {}({base})",
+                opcode.mnemonic(),
+                imm as u16 as i16
+            ),
+            DecodedInsn::Unknown(word) => write!(f, ".word {word:#010x}"),
+        }
+    }
 }
 
-#[derive(Copy, num_enum::TryFromPrimitive)]
+/// Every MIPS-I opcode, plus the MIPS32 ones this emulator runs, in encoding order. Those not
+/// `implemented` decode as `Unknown` and raise `UnimplementedOpcode` when run
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, num_enum::TryFromPrimitive)]
 #[repr(u32)]
-enum Funct {
-    Sll = 0b000000,
-    SllV = 0b000100,
-    Srl = 0b000010,
-    SrlV = 0b000110,
-    Sra = 0b000011,
-    SraV = 0b000111,
-    Syscall = 0b001100,
-    Add = 0b100000,
-    AddU = 0b100001,
-    Sub = 0b100010,
-    SubU = 0b100011,
-    And = 0b100100,
-    Or = 0b100101,
-    Xor = 0b100110,
-    Nor = 0b100111,
+pub enum Opcode {
+    Reg = 0b000000,
+    RegImm = 0b000001,
+    J = 0b000010,
+    Jal = 0b000011,
+    BEq = 0b000100,
+    BNe = 0b000101,
+    BLeZ = 0b000110,
+    BGtZ = 0b000111,
+    AddI = 0b001000,
+    AddIU = 0b001001,
+    SltI = 0b001010,
+    SltIU = 0b001011,
+    AndI = 0b001100,
+    OrI = 0b001101,
+    XorI = 0b001110,
+    LuI = 0b001111,
+    Cop0 = 0b010000,
+    Cop1 = 0b010001,
+    Cop2 = 0b010010,
+    Cop3 = 0b010011,
+    /// MIPS32 additions such as the three-operand `mul`
+    Special2 = 0b011100,
+    /// MIPS32r2 additions such as `rdhwr`
+    Special3 = 0b011111,
+    Lb = 0b100000,
+    Lh = 0b100001,
+    Lwl = 0b100010,
+    Lw = 0b100011,
+    Lbu = 0b100100,
+    Lhu = 0b100101,
+    Lwr = 0b100110,
+    Sb = 0b101000,
+    Sh = 0b101001,
+    Swl = 0b101010,
+    Sw = 0b101011,
+    Swr = 0b101110,
+    /// Load linked: `lw` that also starts a link for `sc`
+    Ll = 0b110000,
+    Lwc1 = 0b110001,
+    Lwc2 = 0b110010,
+    Lwc3 = 0b110011,
+    /// Store conditional: stores only if the `ll` link holds, then sets `rt` to 1 if it stored
+    /// and 0 if not
+    Sc = 0b111000,
+    Swc1 = 0b111001,
+    Swc2 = 0b111010,
+    Swc3 = 0b111011,
+}
+
+impl Opcode {
+    /// Assembly name. The opcodes that select another table have no instruction of their own and
+    /// are named after the table
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Reg => "special",
+            Opcode::RegImm => "regimm",
+            Opcode::J => "j",
+            Opcode::Jal => "jal",
+            Opcode::BEq => "beq",
+            Opcode::BNe => "bne",
+            Opcode::BLeZ => "blez",
+            Opcode::BGtZ => "bgtz",
+            Opcode::AddI => "addi",
+            Opcode::AddIU => "addiu",
+            Opcode::SltI => "slti",
+            Opcode::SltIU => "sltiu",
+            Opcode::AndI => "andi",
+            Opcode::OrI => "ori",
+            Opcode::XorI => "xori",
+            Opcode::LuI => "lui",
+            Opcode::Cop0 => "cop0",
+            Opcode::Cop1 => "cop1",
+            Opcode::Cop2 => "cop2",
+            Opcode::Cop3 => "cop3",
+            Opcode::Special2 => "special2",
+            Opcode::Special3 => "special3",
+            Opcode::Lb => "lb",
+            Opcode::Lh => "lh",
+            Opcode::Lwl => "lwl",
+            Opcode::Lw => "lw",
+            Opcode::Lbu => "lbu",
+            Opcode::Lhu => "lhu",
+            Opcode::Lwr => "lwr",
+            Opcode::Sb => "sb",
+            Opcode::Sh => "sh",
+            Opcode::Swl => "swl",
+            Opcode::Sw => "sw",
+            Opcode::Swr => "swr",
+            Opcode::Ll => "ll",
+            Opcode::Lwc1 => "lwc1",
+            Opcode::Lwc2 => "lwc2",
+            Opcode::Lwc3 => "lwc3",
+            Opcode::Sc => "sc",
+            Opcode::Swc1 => "swc1",
+            Opcode::Swc2 => "swc2",
+            Opcode::Swc3 => "swc3",
+        }
+    }
+
+    /// Whether `Computer` can run instructions with this opcode
+    pub fn implemented(self) -> bool {
+        !matches!(
+            self,
+            Opcode::BEq
+                | Opcode::BNe
+                | Opcode::SltI
+                | Opcode::SltIU
+                | Opcode::Cop0
+                | Opcode::Cop2
+                | Opcode::Cop3
+                | Opcode::Lb
+                | Opcode::Lh
+                | Opcode::Lwl
+                | Opcode::Lbu
+                | Opcode::Lhu
+                | Opcode::Lwr
+                | Opcode::Sb
+                | Opcode::Sh
+                | Opcode::Swl
+                | Opcode::Swr
+                | Opcode::Lwc2
+                | Opcode::Lwc3
+                | Opcode::Swc2
+                | Opcode::Swc3
+        )
+    }
 }
 
-#[derive(Copy, num_enum::TryFromPrimitive, num_enum::IntoPrimitive)]
+/// The `rt` field of a REGIMM instruction. Branches compare `rs` against zero; traps compare it
+/// against the sign-extended immediate, unsigned for the `U` forms
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
 #[repr(u32)]
-enum SyscallCode {
-    Exit = 10,
+pub enum RegImm {
+    BLtZ = 0b00000,
+    BGeZ = 0b00001,
+    TGeI = 0b01000,
+    TGeIU = 0b01001,
+    TLtI = 0b01010,
+    TLtIU = 0b01011,
+    TEqI = 0b01100,
+    TNeI = 0b01110,
 }
 
-#[derive(thiserror::Error)]
-enum InsnError {
-    #[error("integer overflow")]
-    IntegerOverflow,
+impl RegImm {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            RegImm::BLtZ => "bltz",
+            RegImm::BGeZ => "bgez",
+            RegImm::TGeI => "tgei",
+            RegImm::TGeIU => "tgeiu",
+            RegImm::TLtI => "tlti",
+            RegImm::TLtIU => "tltiu",
+            RegImm::TEqI => "teqi",
+            RegImm::TNeI => "tnei",
+        }
+    }
+}
 
-    #[error("attempted to mutate $zero")]
-    RegMutZero,
+/// The `rs` field of a coprocessor 1 instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum Cop1Fmt {
+    MfC1 = 0b00000,
+    MtC1 = 0b00100,
+    /// `bc1t` and `bc1f`
+    Bc = 0b01000,
+    /// Single-precision arithmetic
+    S = 0b10000,
+    /// Operands are 32-bit integers; only `cvt.s.w`
+    W = 0b10100,
+}
 
-    #[error("invalid opcode {0:#b}")]
-    InvalidOpcode(u32),
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum Cop1Funct {
+    Add = 0b000000,
+    Sub = 0b000001,
+    Mul = 0b000010,
+    Div = 0b000011,
+    CEq = 0b110010,
+    CLt = 0b111100,
+    CLe = 0b111110,
+    /// Convert to single; valid for the `W` format
+    CvtS = 0b100000,
+    /// Convert to word, rounding per the rounding mode
+    CvtW = 0b100100,
+}
 
-    #[error("invalid funct {0:#b}")]
-    InvalidFunct(u32),
+impl Cop1Funct {
+    /// Assembly name without the format suffix
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Cop1Funct::Add => "add",
+            Cop1Funct::Sub => "sub",
+            Cop1Funct::Mul => "mul",
+            Cop1Funct::Div => "div",
+            Cop1Funct::CEq => "c.eq",
+            Cop1Funct::CLt => "c.lt",
+            Cop1Funct::CLe => "c.le",
+            Cop1Funct::CvtS => "cvt.s",
+            Cop1Funct::CvtW => "cvt.w",
+        }
+    }
+}
 
-    #[error("unsupported syscall $v0={0}")]
-    UnsupportedSyscall(u32),
+/// The FCSR rounding modes, in encoding order
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// To nearest, ties to even
+    #[default]
+    Nearest,
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Zero,
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Up,
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Down,
+}
+
+impl RoundingMode {
+    /// `word` as the `f32` this mode rounds it to. Only words beyond 2^24 are inexact
+    fn int_to_f32(self, word: i32) -> f32 {
+        let nearest = word as f32;
+        // Both sides are exact as f64, so this is the rounding error's sign
+        let err = nearest as f64 - word as f64;
+        match self {
+            RoundingMode::Up if err < 0.0 => nearest.next_up(),
+            RoundingMode::Down if err > 0.0 => nearest.next_down(),
+            RoundingMode::Zero if err > 0.0 && word > 0 => nearest.next_down(),
+            RoundingMode::Zero if err < 0.0 && word < 0 => nearest.next_up(),
+            _ => nearest,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::UnsafeFromPrimitive)]
+#[repr(u32)]
+pub enum Reg {
+    Zero = 0,
+    At = 1,
+    V0 = 2,
+    V1 = 3,
+    A0 = 4,
+    A1 = 5,
+    A2 = 6,
+    A3 = 7,
+    T0 = 8,
+    T1 = 9,
+    T2 = 10,
+    T3 = 11,
+    T4 = 12,
+    T5 = 13,
+    T6 = 14,
+    T7 = 15,
+    S0 = 16,
+    S1 = 17,
+    S2 = 18,
+    S3 = 19,
+    S4 = 20,
+    S5 = 21,
+    S6 = 22,
+    S7 = 23,
+    T8 = 24,
+    T9 = 25,
+    K0 = 26,
+    K1 = 27,
+    GP = 28,
+    SP = 29,
+    FP = 30,
+    RA = 31,
+}
+
+impl Reg {
+    /// Every register in encoding order, `$zero` through `$ra`
+    pub fn all() -> impl Iterator<Item = Reg> {
+        (0..32).map(Reg::from_field)
+    }
+
+    /// The register a 5-bit instruction field names; higher bits are ignored
+    fn from_field(n: u32) -> Reg {
+        unsafe { Reg::unchecked_transmute_from(n & 0x1F) }
+    }
+
+    /// The conventional name, without the `$`
+    pub fn abi_name(self) -> &'static str {
+        const NAMES: [&str; 32] = [
+            "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5",
+            "t6", "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1",
+            "gp", "sp", "fp", "ra",
+        ];
+        NAMES[self as usize]
+    }
+}
+
+/// A register operand as written in assembly
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    Gpr(Reg),
+    /// `$f0` through `$f31`
+    Fpr(usize),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown register {0:?}")]
+pub struct UnknownRegister(pub String);
+
+/// Parses `$t0`-style names, `$8`-style numbers and `$f0`-`$f31`
+pub fn parse_register(s: &str) -> Result<Register, UnknownRegister> {
+    let unknown = || UnknownRegister(s.to_string());
+    let name = s.strip_prefix('$').ok_or_else(unknown)?;
+    let number = |digits: &str| match digits.parse::<usize>() {
+        // Reject forms like `$+1` and `$08` that parse() would accept
+        Ok(n) if n < 32 && digits == n.to_string() => Some(n),
+        _ => None,
+    };
+    if let Some(n) = number(name) {
+        return Ok(Register::Gpr(Reg::from_field(n as u32)));
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(number) {
+        return Ok(Register::Fpr(n));
+    }
+    let reg = match name {
+        // SPIM's alternate name for $fp
+        "s8" => Reg::FP,
+        _ => Reg::all()
+            .find(|r| r.abi_name() == name)
+            .ok_or_else(unknown)?,
+    };
+    Ok(Register::Gpr(reg))
+}
+
+/// Accepts what `parse_register` does, except FP registers
+impl FromStr for Reg {
+    type Err = UnknownRegister;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_register(s)? {
+            Register::Gpr(reg) => Ok(reg),
+            Register::Fpr(_) => Err(UnknownRegister(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.abi_name())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum Funct {
+    Sll = 0b000000,
+    SllV = 0b000100,
+    Srl = 0b000010,
+    SrlV = 0b000110,
+    Sra = 0b000011,
+    SraV = 0b000111,
+    /// Jumps to the address in `rs`, which must be in the text segment
+    Jr = 0b001000,
+    Syscall = 0b001100,
+    Break = 0b001101,
+    /// Orders memory accesses; a no-op
+    Sync = 0b001111,
+    MfHi = 0b010000,
+    MfLo = 0b010010,
+    Add = 0b100000,
+    AddU = 0b100001,
+    Sub = 0b100010,
+    SubU = 0b100011,
+    And = 0b100100,
+    Or = 0b100101,
+    Xor = 0b100110,
+    Nor = 0b100111,
+}
+
+impl Funct {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Funct::Sll => "sll",
+            Funct::SllV => "sllv",
+            Funct::Srl => "srl",
+            Funct::SrlV => "srlv",
+            Funct::Sra => "sra",
+            Funct::SraV => "srav",
+            Funct::Jr => "jr",
+            Funct::Syscall => "syscall",
+            Funct::Break => "break",
+            Funct::Sync => "sync",
+            Funct::Add => "add",
+            Funct::AddU => "addu",
+            Funct::Sub => "sub",
+            Funct::SubU => "subu",
+            Funct::And => "and",
+            Funct::Or => "or",
+            Funct::Xor => "xor",
+            Funct::Nor => "nor",
+            Funct::MfHi => "mfhi",
+            Funct::MfLo => "mflo",
+        }
+    }
+}
+
+/// The `funct` field of a SPECIAL2 instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum Special2Funct {
+    /// Adds the signed 64-bit product to HI/LO
+    MAdd = 0b000000,
+    MAddU = 0b000001,
+    /// Low 32 bits of the product, into `rd`
+    Mul = 0b000010,
+    /// Subtracts the signed 64-bit product from HI/LO
+    MSub = 0b000100,
+    MSubU = 0b000101,
+}
+
+impl Special2Funct {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Special2Funct::MAdd => "madd",
+            Special2Funct::MAddU => "maddu",
+            Special2Funct::Mul => "mul",
+            Special2Funct::MSub => "msub",
+            Special2Funct::MSubU => "msubu",
+        }
+    }
+}
+
+/// The `funct` field of a SPECIAL3 instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+pub enum Special3Funct {
+    /// Extracts the field of `rs` at bits `shamt` up, `rd + 1` wide, into the low bits of `rt`
+    Ext = 0b000000,
+    /// Inserts the low bits of `rs` into bits `shamt` through `rd` of `rt`
+    Ins = 0b000100,
+    /// Copies hardware register `rd` into `rt`
+    RdHwr = 0b111011,
+}
+
+impl Special3Funct {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Special3Funct::Ext => "ext",
+            Special3Funct::Ins => "ins",
+            Special3Funct::RdHwr => "rdhwr",
+        }
+    }
+}
+
+/// What a syscall does. A `SyscallTable` decides which `$v0` selects each one; the discriminants
+/// are the numbers SPIM and MARS use
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum SyscallCode {
+    /// `$a0` as a signed decimal, exactly as SPIM prints it: a `-` for negatives and nothing else
+    /// around the digits, not even a newline. Autograders diff this output, so keep it that way
+    PrintInt = 1,
+    PrintFloat = 2,
+    PrintDouble = 3,
+    PrintString = 4,
+    ReadInt = 5,
+    Exit = 10,
+    PrintChar = 11,
+    ReadChar = 12,
+    Exit2 = 17,
+    Time = 30,
+    MidiOut = 31,
+    /// MARS's GUI dialogs, answered by the computer's `Dialogs`. Yes/no/cancel for the string at
+    /// `$a0`, answered in `$a0`
+    ConfirmDialog = 50,
+    /// Answers with the integer in `$a0` and a status in `$a1`
+    InputDialogInt = 51,
+    /// Shows the string at `$a0` with the `MessageKind` in `$a1`
+    MessageDialog = 55,
+    /// Not in SPIM or MARS, so numbered clear of both: reads a line into the `$a1`-byte buffer at
+    /// `$a0`, echoing it to the output when `$a2` is nonzero
+    ReadLine = 100,
+    /// Also custom: flushes buffered output. The read syscalls already flush before reading, so
+    /// prompts show up first
+    Flush = 105,
+}
+
+/// Maps `$v0` values to the syscalls they select. Anything unmapped raises `UnsupportedSyscall`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallTable {
+    codes: HashMap<u32, SyscallCode>,
+}
+
+impl SyscallTable {
+    /// No syscalls at all, to build a course's own convention from
+    pub fn empty() -> Self {
+        Self {
+            codes: HashMap::new(),
+        }
+    }
+
+    /// SPIM's numbers, plus the custom read-line and flush calls
+    pub fn spim() -> Self {
+        let mut table = Self::empty();
+        for code in [
+            SyscallCode::PrintInt,
+            SyscallCode::PrintFloat,
+            SyscallCode::PrintDouble,
+            SyscallCode::PrintString,
+            SyscallCode::ReadInt,
+            SyscallCode::Exit,
+            SyscallCode::PrintChar,
+            SyscallCode::ReadChar,
+            SyscallCode::Exit2,
+            SyscallCode::ReadLine,
+            SyscallCode::Flush,
+        ] {
+            table.insert(code as u32, code);
+        }
+        table
+    }
+
+    /// SPIM's numbers plus MARS's `time`, `midi_out` and dialogs. The default
+    pub fn mars() -> Self {
+        let mut table = Self::spim();
+        for code in [
+            SyscallCode::Time,
+            SyscallCode::MidiOut,
+            SyscallCode::ConfirmDialog,
+            SyscallCode::InputDialogInt,
+            SyscallCode::MessageDialog,
+        ] {
+            table.insert(code as u32, code);
+        }
+        table
+    }
+
+    /// Makes `number` select `code`, replacing whatever it selected before
+    pub fn insert(&mut self, number: u32, code: SyscallCode) {
+        self.codes.insert(number, code);
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn remove(&mut self, number: u32) {
+        self.codes.remove(&number);
+    }
+
+    pub fn get(&self, number: u32) -> Option<SyscallCode> {
+        self.codes.get(&number).copied()
+    }
+}
+
+impl Default for SyscallTable {
+    fn default() -> Self {
+        Self::mars()
+    }
+}
+
+/// Configures a `Computer` before it's built. Every option defaults to what `Computer::new` uses
+#[derive(Debug)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub struct ComputerBuilder {
+    text_base: u32,
+    data_base: u32,
+    poison_registers: bool,
+    lenient_decode: bool,
+    strict_zero: bool,
+    strict_mul: bool,
+    strict_memory: bool,
+    self_modifying: bool,
+    detect_stale_code: bool,
+    eof_policy: EofPolicy,
+    bad_int_policy: BadIntPolicy,
+    unicode_print_char: bool,
+    syscall_table: SyscallTable,
+    rounding: RoundingMode,
+    endianness: Endianness,
+    pc_history: usize,
+    mem_trace: bool,
+    cycle_costs: CycleCosts,
+    clock: Option<Box<dyn Clock>>,
+    dialogs: Option<Box<dyn Dialogs>>,
+}
+
+impl Default for ComputerBuilder {
+    fn default() -> Self {
+        Self {
+            text_base: TEXT_BASE,
+            data_base: DATA_BASE,
+            poison_registers: false,
+            lenient_decode: false,
+            strict_zero: false,
+            strict_mul: false,
+            strict_memory: false,
+            self_modifying: false,
+            detect_stale_code: false,
+            eof_policy: EofPolicy::default(),
+            bad_int_policy: BadIntPolicy::default(),
+            unicode_print_char: false,
+            syscall_table: SyscallTable::default(),
+            rounding: RoundingMode::default(),
+            endianness: Endianness::default(),
+            pc_history: 0,
+            mem_trace: false,
+            cycle_costs: CycleCosts::default(),
+            clock: None,
+            dialogs: None,
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+impl ComputerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text_base(mut self, text_base: u32) -> Self {
+        self.text_base = text_base;
+        self
+    }
+
+    /// Where `load_data` puts the data segment
+    pub fn data_base(mut self, data_base: u32) -> Self {
+        self.data_base = data_base;
+        self
+    }
+
+    pub fn poison_registers(mut self, on: bool) -> Self {
+        self.poison_registers = on;
+        self
+    }
+
+    pub fn lenient_decode(mut self, on: bool) -> Self {
+        self.lenient_decode = on;
+        self
+    }
+
+    pub fn strict_zero(mut self, on: bool) -> Self {
+        self.strict_zero = on;
+        self
+    }
+
+    pub fn strict_mul(mut self, on: bool) -> Self {
+        self.strict_mul = on;
+        self
+    }
+
+    pub fn strict_memory(mut self, on: bool) -> Self {
+        self.strict_memory = on;
+        self
+    }
+
+    pub fn self_modifying(mut self, on: bool) -> Self {
+        self.self_modifying = on;
+        self
+    }
+
+    pub fn detect_stale_code(mut self, on: bool) -> Self {
+        self.detect_stale_code = on;
+        self
+    }
+
+    pub fn eof_policy(mut self, policy: EofPolicy) -> Self {
+        self.eof_policy = policy;
+        self
+    }
+
+    pub fn bad_int_policy(mut self, policy: BadIntPolicy) -> Self {
+        self.bad_int_policy = policy;
+        self
+    }
+
+    pub fn unicode_print_char(mut self, on: bool) -> Self {
+        self.unicode_print_char = on;
+        self
+    }
+
+    pub fn syscall_table(mut self, table: SyscallTable) -> Self {
+        self.syscall_table = table;
+        self
+    }
+
+    pub fn rounding_mode(mut self, mode: RoundingMode) -> Self {
+        self.rounding = mode;
+        self
+    }
+
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    pub fn pc_history(mut self, len: usize) -> Self {
+        self.pc_history = len;
+        self
+    }
+
+    pub fn mem_trace(mut self, on: bool) -> Self {
+        self.mem_trace = on;
+        self
+    }
+
+    pub fn cycle_costs(mut self, costs: CycleCosts) -> Self {
+        self.cycle_costs = costs;
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    pub fn dialogs(mut self, dialogs: impl Dialogs + 'static) -> Self {
+        self.dialogs = Some(Box::new(dialogs));
+        self
+    }
+
+    pub fn build(self, program: Vec<Insn>) -> Computer {
+        let mut computer = Computer::with_text_base(program, self.text_base);
+        computer.data_base = self.data_base;
+        computer.set_poison_registers(self.poison_registers);
+        computer.set_lenient_decode(self.lenient_decode);
+        computer.set_strict_zero(self.strict_zero);
+        computer.set_strict_mul(self.strict_mul);
+        computer.set_strict_memory(self.strict_memory);
+        computer.set_self_modifying(self.self_modifying);
+        computer.set_detect_stale_code(self.detect_stale_code);
+        computer.set_eof_policy(self.eof_policy);
+        computer.set_bad_int_policy(self.bad_int_policy);
+        computer.set_unicode_print_char(self.unicode_print_char);
+        computer.set_syscall_table(self.syscall_table);
+        computer.set_rounding_mode(self.rounding);
+        computer.set_endianness(self.endianness);
+        computer.set_pc_history(self.pc_history);
+        computer.set_mem_trace(self.mem_trace);
+        computer.set_cycle_costs(self.cycle_costs);
+        if let Some(clock) = self.clock {
+            computer.clock = clock;
+        }
+        if let Some(dialogs) = self.dialogs {
+            computer.dialogs = dialogs;
+        }
+        computer
+    }
+}
+
+/// A simple timing model: how many cycles each instruction costs. Anything not given a cost takes
+/// 1 cycle
+#[derive(Debug, Clone, Default)]
+pub struct CycleCosts {
+    opcodes: HashMap<Opcode, u64>,
+    /// For SPECIAL instructions, which all share `Opcode::Reg`
+    functs: HashMap<Funct, u64>,
+}
+
+impl CycleCosts {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_opcode(&mut self, opcode: Opcode, cycles: u64) {
+        self.opcodes.insert(opcode, cycles);
+    }
+
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn set_funct(&mut self, funct: Funct, cycles: u64) {
+        self.functs.insert(funct, cycles);
+    }
+
+    pub fn cost(&self, insn: Insn) -> u64 {
+        let cost = match insn.opcode() {
+            Ok(Opcode::Reg) => insn.funct().ok().and_then(|f| self.functs.get(&f)),
+            Ok(opcode) => self.opcodes.get(&opcode),
+            Err(_) => None,
+        };
+        cost.copied().unwrap_or(1)
+    }
+}
+
+/// One load or store, as recorded by the memory trace
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemAccess {
+    /// Address of the instruction making the access
+    pub pc: u32,
+    /// Effective address
+    pub addr: u32,
+    /// The value loaded or stored
+    pub value: u32,
+    pub is_write: bool,
+    /// In bytes
+    pub size: u32,
+}
+
+/// What differs between two machines, as found by `diff_state`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub struct StateDiff {
+    /// GPRs that differ, in encoding order
+    pub registers: Vec<RegDiff>,
+    /// Memory words that differ, in address order. The text segment isn't compared
+    pub memory: Vec<WordDiff>,
+}
+
+impl StateDiff {
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub struct RegDiff {
+    pub reg: Reg,
+    /// The value in the first machine
+    pub old: u32,
+    /// The value in the second
+    pub new: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub struct WordDiff {
+    pub addr: u32,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// The registers and memory words whose values differ from `a` to `b`, e.g. to compare a
+/// program's final state against a reference run. Takes time proportional to the memory either
+/// machine has written; a word one machine never wrote counts as 0
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub fn diff_state(a: &Computer, b: &Computer) -> StateDiff {
+    let registers = Reg::all()
+        .filter(|&reg| a.ru(reg) != b.ru(reg))
+        .map(|reg| RegDiff {
+            reg,
+            old: a.ru(reg),
+            new: b.ru(reg),
+        })
+        .collect();
+    let word = |c: &Computer, addr| c.mem.get(&addr).copied().unwrap_or(0);
+    // Each address once: everything in `a`, then what only `b` has. Only the differences get sorted
+    let addrs = a
+        .mem
+        .keys()
+        .chain(b.mem.keys().filter(|addr| !a.mem.contains_key(addr)));
+    let mut memory: Vec<WordDiff> = addrs
+        .filter_map(|&addr| {
+            let (old, new) = (word(a, addr), word(b, addr));
+            (old != new).then_some(WordDiff { addr, old, new })
+        })
+        .collect();
+    memory.sort_unstable_by_key(|diff| diff.addr);
+    StateDiff { registers, memory }
+}
+
+/// How register dumps print values
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub enum Radix {
+    #[default]
+    Hex,
+    /// Signed
+    Decimal,
+}
+
+/// Byte order of multibyte values in memory
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte at the lowest address, as in SPIM
+    #[default]
+    Big,
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Little,
+}
+
+/// What the `read_int` and `read_char` syscalls do when the input is exhausted
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// `read_int` returns -1 and `read_char` returns 0
+    #[default]
+    Sentinel,
+    /// Raise `InsnError::UnexpectedEof`
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Error,
+}
+
+/// What `read_int` does with a line that isn't a number
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum BadIntPolicy {
+    /// Return 0, as SPIM does when reading from a file
+    #[default]
+    Zero,
+    /// Read up to this many more lines, as interactive SPIM re-prompts, then raise
+    /// `InsnError::BadIntInput`. The program's own prompt isn't printed again
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    Retry(u32),
+}
+
+/// A problem `Computer::validate` found. Addresses are of the offending instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub enum ValidationError {
+    #[error("{addr:#010x}: {word:#010x} is not a supported instruction")]
+    UnknownInstruction { addr: u32, word: u32 },
+
+    #[error("{addr:#010x}: branch target {target:#010x} is outside the text segment")]
+    BranchOutOfText { addr: u32, target: u32 },
+
+    #[error("{addr:#010x}: writes $zero")]
+    WriteToZero { addr: u32 },
+}
+
+/// How a program that ran to completion finished
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// The status passed to `exit2`, or 0 for `exit` and running off the end
+    pub exit_code: i32,
+    /// Instructions executed in total
+    pub executed: u64,
+}
+
+/// For CLI front-ends. Statuses outside 0-255 are clamped into that range
+impl From<RunOutcome> for ExitCode {
+    fn from(outcome: RunOutcome) -> Self {
+        ExitCode::from(outcome.exit_code.clamp(0, 255) as u8)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(not(feature = "internals"), allow(dead_code))]
+pub enum StopReason {
+    /// The pc reached the contained breakpoint address
+    Breakpoint(u32),
+    Exited,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepResult {
+    /// The step budget ran out before the program finished
+    Running,
+    Exited,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{error} after {executed} instructions")]
+pub struct RunError {
+    pub error: InsnError,
+    pub executed: u64,
+    /// Addresses of the most recent instructions, oldest first, ending with the one that failed
+    pub backtrace: Vec<u32>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InsnError {
+    #[error("integer overflow")]
+    IntegerOverflow,
+
+    #[error("attempted to mutate $zero")]
+    RegMutZero,
+
+    #[error("read of uninitialized register {0:?}")]
+    UninitializedRegister(Reg),
+
+    #[error("invalid opcode {0:#b}")]
+    InvalidOpcode(u32),
+
+    #[error("{} is not implemented", .0.mnemonic())]
+    UnimplementedOpcode(Opcode),
+
+    #[error("invalid funct {0:#b}")]
+    InvalidFunct(u32),
+
+    #[error("invalid REGIMM selector {0:#b}")]
+    InvalidRegImm(u32),
+
+    #[error("invalid fmt {0:#b}")]
+    InvalidFmt(u32),
+
+    #[error("no hardware register ${0}")]
+    InvalidHardwareRegister(u32),
+
+    #[error("unsupported syscall $v0={0}")]
+    UnsupportedSyscall(u32),
+
+    #[error("trap")]
+    Trap,
+
+    #[error("break {0:#x}")]
+    Breakpoint(u32),
+
+    #[error("branch target {0:#010x} is outside the text segment")]
+    BranchOutOfText(u32),
+
+    #[error("instruction at {0:#010x} was overwritten after loading")]
+    StaleInstructionCache(u32),
+
+    #[error("store to text segment address {0:#010x}")]
+    WriteToTextSegment(u32),
+
+    #[error("read of uninitialized memory at {0:#010x}")]
+    UninitializedMemoryRead(u32),
+
+    #[error("unaligned memory access at {0:#010x}")]
+    UnalignedAccess(u32),
+
+    #[error("no FP register $f{0}")]
+    NoSuchFpRegister(usize),
+
+    #[error("double-precision operand $f{0} is not an even register")]
+    OddFpRegister(usize),
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("timed out")]
+    Timeout,
+
+    #[error("read past end of input")]
+    UnexpectedEof,
+
+    #[error("{0:?} is not an integer")]
+    BadIntInput(String),
+
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::IoEvent;
+    use crate::dialog::{Confirm, IntInput, MessageKind};
+    use proptest::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    /// Output sink the test keeps a handle to after handing it to a `Computer`
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    /// Runs `program` and returns everything it printed
+    fn output_of(program: Vec<Insn>) -> String {
+        let out = SharedBuf::default();
+        let mut c = Computer::new(program);
+        c.set_output(out.clone());
+        c.run().unwrap();
+        out.contents()
+    }
+
+    fn r(funct: Funct, rd: Reg, rs: Reg, rt: Reg, shamt: u32) -> Insn {
+        Insn((rs as u32) << 21 | (rt as u32) << 16 | (rd as u32) << 11 | shamt << 6 | funct as u32)
+    }
+
+    fn i(opcode: Opcode, rt: Reg, rs: Reg, imm: i32) -> Insn {
+        Insn((opcode as u32) << 26 | (rs as u32) << 21 | (rt as u32) << 16 | (imm as u32 & 0xFFFF))
+    }
+
+    /// `lui` + `ori` into `reg`
+    fn li(reg: Reg, val: u32) -> [Insn; 2] {
+        [
+            i(Opcode::LuI, reg, Reg::Zero, (val >> 16) as i32),
+            i(Opcode::OrI, reg, reg, (val & 0xFFFF) as i32),
+        ]
+    }
+
+    fn syscall() -> Insn {
+        Insn(Funct::Syscall as u32)
+    }
+
+    fn fop(funct: Cop1Funct, fd: usize, fs: usize, ft: usize) -> Insn {
+        Insn(
+            (Opcode::Cop1 as u32) << 26
+                | (Cop1Fmt::S as u32) << 21
+                | (ft as u32) << 16
+                | (fs as u32) << 11
+                | (fd as u32) << 6
+                | funct as u32,
+        )
+    }
+
+    fn mtc1(rt: Reg, fs: usize) -> Insn {
+        Insn(
+            (Opcode::Cop1 as u32) << 26
+                | (Cop1Fmt::MtC1 as u32) << 21
+                | (rt as u32) << 16
+                | (fs as u32) << 11,
+        )
+    }
+
+    fn mfc1(rt: Reg, fs: usize) -> Insn {
+        Insn((Opcode::Cop1 as u32) << 26 | (rt as u32) << 16 | (fs as u32) << 11)
+    }
+
+    fn bc1(taken_if: bool, offset: i32) -> Insn {
+        Insn(
+            (Opcode::Cop1 as u32) << 26
+                | (Cop1Fmt::Bc as u32) << 21
+                | (taken_if as u32) << 16
+                | (offset as u32 & 0xFFFF),
+        )
+    }
+
+    /// Counts `$t0` up while `$f1` (stepped by 1.0) stays below 5.0. Returns the program and the
+    /// index of the `addiu` inside the loop
+    fn counting_loop() -> (Vec<Insn>, usize) {
+        let mut program = vec![];
+        program.extend(li(Reg::T1, 1.0f32.to_bits()));
+        program.push(mtc1(Reg::T1, 3));
+        program.extend(li(Reg::T1, 5.0f32.to_bits()));
+        program.push(mtc1(Reg::T1, 2));
+        let body = program.len();
+        program.push(fop(Cop1Funct::Add, 1, 1, 3));
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, 1));
+        program.push(fop(Cop1Funct::CLt, 0, 1, 2));
+        program.push(bc1(true, -4));
+        (program, body + 1)
+    }
+
+    /// Stores over its last instruction, `addiu $t1, $t1, 1` at `TEXT_BASE + 24`, before it runs,
+    /// replacing it with `addiu $t1, $zero, 9`
+    fn self_modifying_program() -> Vec<Insn> {
+        let mut program = li(Reg::T0, i(Opcode::AddIU, Reg::T1, Reg::Zero, 9).0).to_vec();
+        program.push(mtc1(Reg::T0, 0));
+        program.extend(li(Reg::T2, TEXT_BASE + 24));
+        program.push(i(Opcode::Swc1, Reg::Zero, Reg::T2, 0));
+        program.push(i(Opcode::AddIU, Reg::T1, Reg::T1, 1));
+        program
+    }
+
+    #[test]
+    fn immediate_alu_writes_rt() {
+        let mut program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, -1),
+            i(Opcode::AddI, Reg::T1, Reg::Zero, 0x7FFF),
+            i(Opcode::AndI, Reg::T2, Reg::T0, 0x8001),
+            i(Opcode::XorI, Reg::T3, Reg::T2, 1),
+        ];
+        program.extend(li(Reg::T4, 0xDEAD_BEEF));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T0), 0xFFFF_FFFF);
+        assert_eq!(c.ru(Reg::T1), 0x7FFF);
+        assert_eq!(c.ru(Reg::T2), 0x8001);
+        assert_eq!(c.ru(Reg::T3), 0x8000);
+        assert_eq!(c.ru(Reg::T4), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn addiu_wraps_without_trapping() {
+        let mut program = li(Reg::T0, i32::MAX as u32).to_vec();
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, 1));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T0), 0x8000_0000);
+    }
+
+    #[test]
+    fn breakpoint_mid_loop() {
+        let (program, addiu) = counting_loop();
+        let bp = TEXT_BASE + addiu as u32 * 4;
+        let bps = BTreeSet::from([bp]);
+        let mut c = Computer::new(program);
+        assert!(matches!(c.run_until_breakpoint(&bps), Ok(StopReason::Breakpoint(a)) if a == bp));
+        assert_eq!(c.ru(Reg::T0), 0);
+        assert!(matches!(c.run_until_breakpoint(&bps), Ok(StopReason::Breakpoint(a)) if a == bp));
+        assert_eq!(c.ru(Reg::T0), 1);
+        assert!(matches!(
+            c.run_until_breakpoint(&BTreeSet::new()),
+            Ok(StopReason::Exited)
+        ));
+        assert_eq!(c.ru(Reg::T0), 5);
+    }
+
+    #[test]
+    fn breakpoint_at_entry() {
+        let (program, _) = counting_loop();
+        let bps = BTreeSet::from([TEXT_BASE]);
+        let mut c = Computer::new(program);
+        assert!(matches!(
+            c.run_until_breakpoint(&bps),
+            Ok(StopReason::Breakpoint(TEXT_BASE))
+        ));
+        assert_eq!(c.pc_addr(), TEXT_BASE);
+        assert!(matches!(
+            c.run_until_breakpoint(&bps),
+            Ok(StopReason::Exited)
+        ));
+    }
+
+    #[test]
+    fn break_halts_with_code() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 1),
+            Insn(0xABCDE << 6 | Funct::Break as u32),
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 2),
+        ];
+        let mut c = Computer::new(program);
+        assert!(matches!(
+            c.run(),
+            Err(RunError {
+                error: InsnError::Breakpoint(0xABCDE),
+                ..
+            })
+        ));
+        assert_eq!(c.ru(Reg::T0), 1);
+        assert_eq!(c.pc_addr(), TEXT_BASE + 4);
+
+        // Jumping back after exit runs again
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::T0, 1),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 10),
+            syscall(),
+        ];
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert!(c.finished());
+        c.set_pc(TEXT_BASE).unwrap();
+        assert!(!c.finished());
+        c.step().unwrap();
+        assert_eq!(c.ru(Reg::T0), 2);
+    }
+
+    #[test]
+    fn load_data_at_data_base() {
+        let print_string = |addr: u32| {
+            let mut program = li(Reg::A0, addr).to_vec();
+            program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 4));
+            program.push(syscall());
+            program
+        };
+        let out = SharedBuf::default();
+        let mut c = Computer::new(print_string(DATA_BASE));
+        c.set_output(out.clone());
+        c.load_data(b"hi\0").unwrap();
+        c.run().unwrap();
+        assert_eq!(out.contents(), "hi");
+        assert_eq!(c.data_base(), DATA_BASE);
+
+        // Laid out again in the new byte order, and again after a reset clears memory
+        let base = 0x1000_0000;
+        let out = SharedBuf::default();
+        let mut c = ComputerBuilder::new()
+            .data_base(base)
+            .build(print_string(base + 1));
+        c.set_output(out.clone());
+        c.load_data(b"xyz\0").unwrap();
+        c.set_endianness(Endianness::Little);
+        assert_eq!(c.mem()[&base], u32::from_le_bytes(*b"xyz\0"));
+        c.run().unwrap();
+        c.reset();
+        c.run().unwrap();
+        assert_eq!(out.contents(), "yzyz");
+
+        let mut c = ComputerBuilder::new()
+            .data_base(TEXT_BASE - 2)
+            .build(vec![Insn(0)]);
+        assert!(matches!(
+            c.load_data(b"abcd"),
+            Err(InsnError::WriteToTextSegment(addr)) if addr == TEXT_BASE
+        ));
+        assert!(c.mem().is_empty());
+    }
+
+    #[test]
+    fn pc_addr_uses_text_base() {
+        let nop = i(Opcode::OrI, Reg::T0, Reg::T0, 0);
+        let mut c = Computer::with_text_base(vec![nop; 3], 0x0000_1000);
+        assert_eq!(c.pc_addr(), 0x1000);
+        c.run_steps(2).unwrap();
+        assert_eq!(c.pc_addr(), 0x1008);
+
+        let mut c = Computer::with_text_base(vec![nop; 2], 0xFFFF_FFFC);
+        c.step().unwrap();
+        assert_eq!(c.pc_addr(), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn decode_never_panics(word: u32) {
+            decode(word);
+        }
+    }
+
+    #[test]
+    fn decode_is_total() {
+        for word in [0, u32::MAX, 0xFC00_0000, 0x0000_003F, 0x4400_003F] {
+            decode(word);
+        }
+        assert_eq!(decode(u32::MAX), DecodedInsn::Unknown(u32::MAX));
+        assert_eq!(
+            decode(i(Opcode::OrI, Reg::T0, Reg::T1, 0xBEEF).0),
+            DecodedInsn::Imm {
+                opcode: Opcode::OrI,
+                rs: Reg::T1,
+                rt: Reg::T0,
+                imm: 0xBEEF
+            }
+        );
+    }
+
+    /// Loads `bits` into `$f12` (and `$f13` for the high word) and makes syscall `code`
+    fn print_fp(code: u32, bits: u64) -> String {
+        let mut program = li(Reg::T0, bits as u32).to_vec();
+        program.push(mtc1(Reg::T0, 12));
+        program.extend(li(Reg::T0, (bits >> 32) as u32));
+        program.push(mtc1(Reg::T0, 13));
+        program.extend(li(Reg::V0, code));
+        program.push(syscall());
+        output_of(program)
+    }
+
+    #[test]
+    fn print_float_and_double() {
+        assert_eq!(print_fp(2, 1.5f32.to_bits() as u64), "1.5");
+        assert_eq!(print_fp(2, 2.0f32.to_bits() as u64), "2.0");
+        assert_eq!(print_fp(2, f32::INFINITY.to_bits() as u64), "Infinity");
+        assert_eq!(print_fp(2, f32::NEG_INFINITY.to_bits() as u64), "-Infinity");
+        assert_eq!(print_fp(2, f32::NAN.to_bits() as u64), "NaN");
+        assert_eq!(print_fp(3, 0.1f64.to_bits()), "0.1");
+        assert_eq!(print_fp(3, f64::INFINITY.to_bits()), "Infinity");
+    }
+
+    /// Runs `funct` on `$f1` = `a` and `$f2` = `b` and returns `$f0`
+    fn fp_arith(funct: Cop1Funct, a: f32, b: f32) -> f32 {
+        let mut program = li(Reg::T0, a.to_bits()).to_vec();
+        program.push(mtc1(Reg::T0, 1));
+        program.extend(li(Reg::T0, b.to_bits()));
+        program.push(mtc1(Reg::T0, 2));
+        program.push(fop(funct, 0, 1, 2));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        f32::from_bits(c.fpr(0).unwrap())
+    }
+
+    #[test]
+    fn fp_single_arithmetic() {
+        assert_eq!(fp_arith(Cop1Funct::Add, 1.5, 2.25), 3.75);
+        assert_eq!(fp_arith(Cop1Funct::Sub, 1.5, 2.25), -0.75);
+        assert_eq!(fp_arith(Cop1Funct::Mul, 1.5, -4.0), -6.0);
+        assert_eq!(fp_arith(Cop1Funct::Div, 3.0, 4.0), 0.75);
+        assert_eq!(fp_arith(Cop1Funct::Div, 1.0, 0.0), f32::INFINITY);
+        assert!(fp_arith(Cop1Funct::Add, f32::NAN, 1.0).is_nan());
+    }
+
+    #[test]
+    fn decode_rejects_invalid_cop1_funct() {
+        let add = fop(Cop1Funct::Add, 0, 1, 2).0;
+        assert!(matches!(
+            decode(add),
+            DecodedInsn::Cop1 {
+                fmt: Cop1Fmt::S,
+                funct: Some(Cop1Funct::Add),
+                ..
+            }
+        ));
+        assert_eq!(decode(add | 0x3F), DecodedInsn::Unknown(add | 0x3F));
+        assert!(matches!(
+            decode(mtc1(Reg::T0, 1).0),
+            DecodedInsn::Cop1 { funct: None, .. }
+        ));
+    }
+
+    #[test]
+    fn fp_load_store_round_trip() {
+        let bits = (-3.25f32).to_bits();
+        let mut program = li(Reg::T0, bits).to_vec();
+        program.push(mtc1(Reg::T0, 4));
+        program.extend(li(Reg::SP, 0x7FFF_F000));
+        program.push(i(
+            Opcode::Swc1,
+            unsafe { Reg::unchecked_transmute_from(4) },
+            Reg::SP,
+            -8,
+        ));
+        program.push(i(
+            Opcode::Lwc1,
+            unsafe { Reg::unchecked_transmute_from(6) },
+            Reg::SP,
+            -8,
+        ));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.mem().get(&0x7FFF_EFF8), Some(&bits));
+        assert_eq!(c.fpr(6).unwrap(), bits);
+    }
+
+    #[test]
+    fn fp_load_requires_alignment() {
+        let mut program = li(Reg::T0, 0x1000_0002).to_vec();
+        program.push(i(Opcode::Lwc1, Reg::Zero, Reg::T0, 0));
+        let mut c = Computer::new(program);
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::UnalignedAccess(0x1000_0002)));
+    }
+
+    #[test]
+    fn decode_fp_mem_keeps_raw_ft() {
+        let word = i(Opcode::Lwc1, Reg::T0, Reg::SP, 4).0;
+        assert_eq!(
+            decode(word),
+            DecodedInsn::FpMem {
+                opcode: Opcode::Lwc1,
+                base: Reg::SP,
+                ft: 8,
+                imm: 4
+            }
+        );
+    }
+
+    #[test]
+    fn fp_compare_and_branch() {
+        // $f1 = 1.0, $f2 = 2.0; $t0 counts the instructions bc1t/bc1f don't skip
+        let mut program = li(Reg::T1, 1.0f32.to_bits()).to_vec();
+        program.push(mtc1(Reg::T1, 1));
+        program.extend(li(Reg::T1, 2.0f32.to_bits()));
+        program.push(mtc1(Reg::T1, 2));
+        program.push(fop(Cop1Funct::CLt, 0, 1, 2));
+        program.push(bc1(true, 1));
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, 1));
+        program.push(fop(Cop1Funct::CEq, 0, 1, 2));
+        program.push(bc1(false, 1));
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, 1));
+        program.push(fop(Cop1Funct::CLe, 0, 2, 1));
+        program.push(bc1(true, 1));
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, 1));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T0), 1);
+    }
+
+    #[test]
+    fn branch_outside_program_errors() {
+        let always = fop(Cop1Funct::CEq, 0, 0, 0);
+        let mut c = Computer::new(vec![always, bc1(true, -3)]);
+        assert!(matches!(
+            c.run().unwrap_err().error,
+            InsnError::BranchOutOfText(_)
+        ));
+
+        let mut c = Computer::new(vec![always, bc1(true, 1)]);
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::BranchOutOfText(a) if a == TEXT_BASE + 12));
+
+        let mut c = Computer::new(vec![always, bc1(true, 0)]);
+        c.run().unwrap();
+        assert!(c.finished());
+    }
+
+    #[test]
+    fn error_carries_count_and_backtrace() {
+        let (mut program, addiu) = counting_loop();
+        let after_loop = program.len();
+        program.push(Insn(Funct::Break as u32));
+        let mut c = Computer::new(program);
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::Breakpoint(0)));
+        // Setup, then five passes through the four-instruction loop
+        assert_eq!(err.executed, addiu as u64 - 1 + 5 * 4);
+        assert_eq!(err.backtrace.len(), BACKTRACE_LEN);
+        let addr = |idx: usize| TEXT_BASE + idx as u32 * 4;
+        assert_eq!(err.backtrace.last(), Some(&addr(after_loop)));
+        let loop_pcs: Vec<_> = (addiu - 1..after_loop).map(addr).collect();
+        assert_eq!(
+            &err.backtrace[BACKTRACE_LEN - 5..BACKTRACE_LEN - 1],
+            &loop_pcs[..]
+        );
+        assert_eq!(c.backtrace().collect::<Vec<_>>(), err.backtrace);
+
+        let mut c = Computer::new(vec![Insn(Funct::Break as u32)]);
+        let err = c.run_steps(3).unwrap_err();
+        assert_eq!((err.executed, err.backtrace), (0, vec![TEXT_BASE]));
+    }
+
+    fn rotr(rd: Reg, rt: Reg, shamt: u32) -> Insn {
+        Insn(r(Funct::Srl, rd, Reg::Zero, rt, shamt).0 | 1 << 21)
+    }
+
+    fn rotrv(rd: Reg, rt: Reg, rs: Reg) -> Insn {
+        Insn(r(Funct::SrlV, rd, rs, rt, 0).0 | 1 << 6)
+    }
+
+    #[test]
+    fn rotate_right() {
+        let val = 0x8123_4567;
+        let mut program = li(Reg::T0, val).to_vec();
+        program.push(rotr(Reg::T1, Reg::T0, 0));
+        program.push(rotr(Reg::T2, Reg::T0, 8));
+        program.push(i(Opcode::AddIU, Reg::T3, Reg::Zero, 4));
+        program.push(rotrv(Reg::T4, Reg::T0, Reg::T3));
+        program.push(rotrv(Reg::T5, Reg::T0, Reg::Zero));
+        // Only the low five bits of rs count, so 36 rotates by 4
+        program.push(i(Opcode::AddIU, Reg::T3, Reg::Zero, 36));
+        program.push(rotrv(Reg::T6, Reg::T0, Reg::T3));
+        program.push(r(Funct::Srl, Reg::T7, Reg::Zero, Reg::T0, 8));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), val);
+        assert_eq!(c.ru(Reg::T2), 0x6781_2345);
+        assert_eq!(c.ru(Reg::T4), 0x7812_3456);
+        assert_eq!(c.ru(Reg::T5), val);
+        assert_eq!(c.ru(Reg::T6), 0x7812_3456);
+        assert_eq!(c.ru(Reg::T7), 0x0081_2345);
+    }
+
+    #[test]
+    fn decode_distinguishes_rotates() {
+        let rd = Reg::T1;
+        let rt = Reg::T0;
+        assert_eq!(
+            decode(rotr(rd, rt, 8).0),
+            DecodedInsn::RotR { rd, rt, shamt: 8 }
+        );
+        assert_eq!(
+            decode(rotrv(rd, rt, Reg::T3).0),
+            DecodedInsn::RotRV {
+                rd,
+                rt,
+                rs: Reg::T3
+            }
+        );
+        assert!(matches!(
+            decode(r(Funct::Srl, rd, Reg::Zero, rt, 8).0),
+            DecodedInsn::Reg {
+                funct: Funct::Srl,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn time_reads_injected_clock() {
+        let mut program = li(Reg::V0, 30).to_vec();
+        program.push(syscall());
+        program.extend(li(Reg::V0, 31));
+        program.push(syscall());
+        let mut c = Computer::new(program);
+        c.set_clock(crate::clock::FixedClock(0x0000_0199_2F3A_1B2C));
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::A0), 0x2F3A_1B2C);
+        assert_eq!(c.ru(Reg::A1), 0x0000_0199);
+    }
+
+    #[test]
+    fn run_steps_yields_on_budget() {
+        let mut program = vec![i(Opcode::AddIU, Reg::T0, Reg::T0, 1); 8];
+        program.extend(li(Reg::V0, 10));
+        program.push(syscall());
+        program.push(Insn(Funct::Break as u32));
+        let mut c = Computer::new(program);
+        assert_eq!(c.run_steps(5).unwrap(), StepResult::Running);
+        assert_eq!(c.pc_addr(), TEXT_BASE + 5 * 4);
+        assert_eq!(c.ru(Reg::T0), 5);
+        assert_eq!(c.step().unwrap(), StepResult::Running);
+        assert_eq!(c.run_steps(100).unwrap(), StepResult::Exited);
+        // The exit syscall stops everything, so the trailing break never runs
+        assert_eq!(c.run_steps(100).unwrap(), StepResult::Exited);
+        assert_eq!(c.executed(), 11);
+    }
+
+    /// Runs syscall 100 on `input` with a `len`-byte buffer and returns the output and memory
+    fn read_line(input: &str, len: u32, echo: bool) -> (String, HashMap<u32, u32>) {
+        let mut program = li(Reg::A0, 0x1001_0000).to_vec();
+        program.push(i(Opcode::AddIU, Reg::A1, Reg::Zero, len as i32));
+        program.push(i(Opcode::AddIU, Reg::A2, Reg::Zero, echo as i32));
+        program.extend(li(Reg::V0, 100));
+        program.push(syscall());
+        let out = SharedBuf::default();
+        let mut c = Computer::new(program);
+        c.set_input(io::Cursor::new(input.as_bytes().to_vec()));
+        c.set_output(out.clone());
+        c.run().unwrap();
+        (out.contents(), c.mem().clone())
+    }
+
+    #[test]
+    fn read_line_echo_on_and_off() {
+        let (echoed, mem) = read_line("hi\nrest", 8, true);
+        assert_eq!(echoed, "hi\n");
+        assert_eq!(mem.get(&0x1001_0000), Some(&u32::from_be_bytes(*b"hi\n\0")));
+        let (silent, quiet_mem) = read_line("hi\nrest", 8, false);
+        assert_eq!(silent, "");
+        assert_eq!(quiet_mem, mem);
+    }
+
+    #[test]
+    fn read_line_truncates_to_buffer() {
+        let (echoed, mem) = read_line("hello world\n", 6, true);
+        assert_eq!(echoed, "hello");
+        assert_eq!(mem.get(&0x1001_0000), Some(&u32::from_be_bytes(*b"hell")));
+        assert_eq!(
+            mem.get(&0x1001_0004),
+            Some(&u32::from_be_bytes([b'o', 0, 0, 0]))
+        );
+    }
+
+    #[test]
+    fn registers_in_canonical_order() {
+        let all: Vec<_> = Reg::all().collect();
+        assert_eq!(all.len(), 32);
+        assert_eq!((all[0], all[8], all[31]), (Reg::Zero, Reg::T0, Reg::RA));
+        assert!(all.iter().enumerate().all(|(n, &reg)| reg as usize == n));
+
+        let mut c = Computer::new(vec![i(Opcode::AddIU, Reg::S3, Reg::Zero, 7)]);
+        c.run().unwrap();
+        let regs = c.registers();
+        assert!(regs
+            .iter()
+            .zip(Reg::all())
+            .all(|(&(reg, _), expected)| reg == expected));
+        assert_eq!(regs[Reg::S3 as usize], (Reg::S3, 7));
+        assert_eq!(regs.iter().filter(|&&(_, val)| val != 0).count(), 1);
+    }
+
+    #[test]
+    fn poisoned_register_read_errors() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 3),
+            i(Opcode::AddIU, Reg::T1, Reg::T0, 1),
+            i(Opcode::AddIU, Reg::T2, Reg::T3, 1),
+        ];
+        let mut c = Computer::new(program.clone());
+        c.set_poison_registers(true);
+        assert_eq!(c.ru(Reg::T3), POISON);
+        assert_eq!(c.ru(Reg::Zero), 0);
+        let err = c.run().unwrap_err();
+        assert!(matches!(
+            err.error,
+            InsnError::UninitializedRegister(Reg::T3)
+        ));
+        assert_eq!(c.ru(Reg::T1), 4);
+
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T2), 1);
+    }
+
+    fn regimm(op: RegImm, rs: Reg, imm: i32) -> Insn {
+        Insn(
+            (Opcode::RegImm as u32) << 26
+                | (rs as u32) << 21
+                | (op as u32) << 16
+                | (imm as u32 & 0xFFFF),
+        )
+    }
+
+    /// Whether `op` traps with `$t0` = `val`
+    fn traps(op: RegImm, val: i32, imm: i32) -> bool {
+        let mut program = li(Reg::T0, val as u32).to_vec();
+        program.push(regimm(op, Reg::T0, imm));
+        match Computer::new(program).run() {
+            Ok(_) => false,
+            Err(RunError {
+                error: InsnError::Trap,
+                ..
+            }) => true,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    #[test]
+    fn trap_immediates() {
+        assert!(traps(RegImm::TLtI, -5, -1));
+        assert!(!traps(RegImm::TLtI, 3, -1));
+        assert!(!traps(RegImm::TLtI, -1, -1));
+        // Unsigned, so -5 is huge and -1 sign-extends to 0xFFFFFFFF
+        assert!(!traps(RegImm::TLtIU, -5, 3));
+        assert!(traps(RegImm::TLtIU, -5, -1));
+        assert!(traps(RegImm::TGeI, 7, 7));
+        assert!(!traps(RegImm::TGeI, 6, 7));
+        assert!(traps(RegImm::TGeIU, -1, 7));
+        assert!(traps(RegImm::TEqI, -2, -2));
+        assert!(!traps(RegImm::TEqI, 2, -2));
+        assert!(traps(RegImm::TNeI, 2, -2));
+        assert!(!traps(RegImm::TNeI, -2, -2));
+    }
+
+    /// Whether the zero-relative branch `insn` (testing `$t0` = `val`) skips the next instruction
+    fn branches(insn: fn(Reg) -> Insn, val: i32) -> bool {
+        let mut program = li(Reg::T0, val as u32).to_vec();
+        program.push(insn(Reg::T0));
+        program.push(i(Opcode::AddIU, Reg::T1, Reg::Zero, 1));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        c.ru(Reg::T1) == 0
+    }
+
+    #[test]
+    fn zero_relative_branches() {
+        let bltz = |rs| regimm(RegImm::BLtZ, rs, 1);
+        let bgez = |rs| regimm(RegImm::BGeZ, rs, 1);
+        let blez = |rs| i(Opcode::BLeZ, Reg::Zero, rs, 1);
+        let bgtz = |rs| i(Opcode::BGtZ, Reg::Zero, rs, 1);
+        for (insn, expected) in [
+            (bltz as fn(Reg) -> Insn, [false, false, true]),
+            (bgez, [true, true, false]),
+            (blez, [false, true, true]),
+            (bgtz, [true, false, false]),
+        ] {
+            assert_eq!([5, 0, -5].map(|val| branches(insn, val)), expected);
+        }
+    }
+
+    /// Runs the read syscall `code` on `input`, returning `$v0`
+    fn read_syscall(code: u32, input: &str, policy: EofPolicy) -> Result<u32, InsnError> {
+        let mut c = Computer::new(vec![
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, code as i32),
+            syscall(),
+        ]);
+        c.set_input(io::Cursor::new(input.as_bytes().to_vec()));
+        c.set_eof_policy(policy);
+        c.run().map_err(|e| e.error)?;
+        Ok(c.ru(Reg::V0))
+    }
+
+    #[test]
+    fn read_int_and_char() {
+        assert_eq!(
+            read_syscall(5, " -42\n", EofPolicy::Error).unwrap(),
+            -42i32 as u32
+        );
+        assert_eq!(
+            read_syscall(12, "xy", EofPolicy::Error).unwrap(),
+            b'x' as u32
+        );
+    }
+
+    #[test]
+    fn eof_sentinels() {
+        assert_eq!(
+            read_syscall(5, "", EofPolicy::Sentinel).unwrap(),
+            -1i32 as u32
+        );
+        assert_eq!(read_syscall(12, "", EofPolicy::Sentinel).unwrap(), 0);
+    }
+
+    #[test]
+    fn eof_errors() {
+        for code in [5, 12] {
+            assert!(matches!(
+                read_syscall(code, "", EofPolicy::Error),
+                Err(InsnError::UnexpectedEof)
+            ));
+        }
+    }
+
+    #[test]
+    fn reset_reruns_identically() {
+        // The loop only runs to 5.0 if $f1 starts at 0, so a stale $f1 would change the output
+        let (mut program, _) = counting_loop();
+        program.push(fop(Cop1Funct::Add, 12, 1, 31));
+        program.extend(li(Reg::V0, 2));
+        program.push(syscall());
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 10));
+        program.push(syscall());
+        let out = SharedBuf::default();
+        let mut c = Computer::new(program);
+        c.set_output(out.clone());
+        c.run().unwrap();
+        let executed = c.executed();
+        c.reset();
+        assert_eq!(c.executed(), 0);
+        c.run().unwrap();
+        assert_eq!(out.contents(), "5.05.0");
+        assert_eq!(c.executed(), executed);
+    }
+
+    #[test]
+    fn pc_history() {
+        let (program, addiu) = counting_loop();
+        let addiu = TEXT_BASE + addiu as u32 * 4;
+        let mut c = Computer::new(program.clone());
+        c.run().unwrap();
+        assert!(c.recent_pcs().is_empty());
+
+        let mut c = Computer::new(program);
+        c.set_pc_history(3);
+        c.run_steps(7).unwrap();
+        assert_eq!(
+            c.recent_pcs(),
+            [TEXT_BASE + 16, TEXT_BASE + 20, TEXT_BASE + 24]
+        );
+        c.run().unwrap();
+        // The last iteration's addiu, fp compare and fall-through bc1t
+        assert_eq!(c.recent_pcs(), [addiu, addiu + 4, addiu + 8]);
+    }
+
+    #[test]
+    fn fpr_widths() {
+        let mut c = Computer::new(vec![]);
+        c.set_fpr_f32(3, -1.25).unwrap();
+        assert_eq!(c.fpr_f32(3).unwrap(), -1.25);
+        assert_eq!(c.fpr(3).unwrap(), (-1.25f32).to_bits());
+        c.set_fpr_f64(4, 0.1).unwrap();
+        assert_eq!(c.fpr_f64(4).unwrap(), 0.1);
+        assert_eq!(c.fpr(5).unwrap(), (0.1f64.to_bits() >> 32) as u32);
+        assert!(matches!(c.fpr_f64(5), Err(InsnError::OddFpRegister(5))));
+        assert!(matches!(
+            c.set_fpr_f64(5, 1.0),
+            Err(InsnError::OddFpRegister(5))
+        ));
+        assert!(matches!(c.fpr(32), Err(InsnError::NoSuchFpRegister(32))));
+        assert!(matches!(
+            c.fpr_f32(32),
+            Err(InsnError::NoSuchFpRegister(32))
+        ));
+        assert!(matches!(
+            c.set_fpr_f32(40, 1.0),
+            Err(InsnError::NoSuchFpRegister(40))
+        ));
+        assert!(matches!(
+            c.fpr_f64(32),
+            Err(InsnError::NoSuchFpRegister(32))
+        ));
+        assert!(matches!(
+            c.set_fpr_f64(34, 1.0),
+            Err(InsnError::NoSuchFpRegister(34))
+        ));
+    }
+
+    #[test]
+    fn mtc1_mfc1_preserve_bits() {
+        // A signalling NaN with a payload would be quieted by any float round trip
+        for bits in [0x7F80_0001, 0x8000_0000, 0x1234_5678] {
+            let mut program = li(Reg::T0, bits).to_vec();
+            program.push(mtc1(Reg::T0, 7));
+            program.push(mfc1(Reg::T1, 7));
+            let mut c = Computer::new(program);
+            c.run().unwrap();
+            assert_eq!(c.fpr(7).unwrap(), bits);
+            assert_eq!(c.ru(Reg::T1), bits);
+        }
+    }
+
+    fn cvt(fmt: Cop1Fmt, funct: Cop1Funct, bits: u32, mode: RoundingMode) -> u32 {
+        let mut program = li(Reg::T0, bits).to_vec();
+        program.push(mtc1(Reg::T0, 1));
+        program.push(Insn(
+            (Opcode::Cop1 as u32) << 26 | (fmt as u32) << 21 | 1 << 11 | 2 << 6 | funct as u32,
+        ));
+        let mut c = Computer::new(program);
+        c.set_rounding_mode(mode);
+        c.run().unwrap();
+        c.fpr(2).unwrap()
+    }
+
+    #[test]
+    fn int_float_conversions() {
+        let (s_w, w_s) = ((Cop1Fmt::W, Cop1Funct::CvtS), (Cop1Fmt::S, Cop1Funct::CvtW));
+        let to_f32 = |word: i32, mode| f32::from_bits(cvt(s_w.0, s_w.1, word as u32, mode));
+        let to_word = |val: f32, mode| cvt(w_s.0, w_s.1, val.to_bits(), mode) as i32;
+        assert_eq!(to_f32(5, RoundingMode::Nearest), 5.0);
+        assert_eq!(to_f32(-7, RoundingMode::Zero), -7.0);
+        // 2^24 + 1 sits between two floats
+        assert_eq!(to_f32(16_777_217, RoundingMode::Up), 16_777_218.0);
+        assert_eq!(to_f32(16_777_217, RoundingMode::Zero), 16_777_216.0);
+        assert_eq!(to_word(3.7, RoundingMode::Nearest), 4);
+        assert_eq!(to_word(3.7, RoundingMode::Zero), 3);
+        assert_eq!(to_word(2.5, RoundingMode::Nearest), 2);
+        assert_eq!(to_word(-3.2, RoundingMode::Down), -4);
+        assert_eq!(to_word(f32::NAN, RoundingMode::Nearest), i32::MAX);
+        assert_eq!(to_word(3e9, RoundingMode::Nearest), i32::MAX);
+    }
+
+    #[test]
+    fn cvt_decode_checks_format() {
+        let word = |fmt: Cop1Fmt, funct: Cop1Funct| {
+            (Opcode::Cop1 as u32) << 26 | (fmt as u32) << 21 | funct as u32
+        };
+        assert!(matches!(
+            decode(word(Cop1Fmt::W, Cop1Funct::CvtS)),
+            DecodedInsn::Cop1 {
+                funct: Some(Cop1Funct::CvtS),
+                ..
+            }
+        ));
+        assert!(matches!(
+            decode(word(Cop1Fmt::W, Cop1Funct::Add)),
+            DecodedInsn::Unknown(_)
+        ));
+        assert!(matches!(
+            decode(word(Cop1Fmt::S, Cop1Funct::CvtS)),
+            DecodedInsn::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn display_decoded() {
+        let trace = format!(
+            "{:#010x}  {}",
+            TEXT_BASE + 16,
+            decode(i(Opcode::AddI, Reg::T0, Reg::T0, 1).0)
+        );
+        assert_eq!(trace, "0x00400010  addi $t0, $t0, 1");
+        let shown = |insn: Insn| decode(insn.0).to_string();
+        assert_eq!(
+            shown(r(Funct::AddU, Reg::V0, Reg::A0, Reg::A1, 0)),
+            "addu $v0, $a0, $a1"
+        );
+        assert_eq!(
+            shown(r(Funct::Sll, Reg::T1, Reg::Zero, Reg::T2, 3)),
+            "sll $t1, $t2, 3"
+        );
+        assert_eq!(
+            shown(rotrv(Reg::S0, Reg::S1, Reg::S2)),
+            "rotrv $s0, $s1, $s2"
+        );
+        assert_eq!(
+            shown(i(Opcode::AddIU, Reg::SP, Reg::SP, -8)),
+            "addiu $sp, $sp, -8"
+        );
+        assert_eq!(
+            shown(i(Opcode::LuI, Reg::At, Reg::Zero, 0x1001)),
+            "lui $at, 0x1001"
+        );
+        assert_eq!(shown(regimm(RegImm::BGeZ, Reg::T0, -2)), "bgez $t0, -2");
+        assert_eq!(shown(fop(Cop1Funct::Div, 0, 2, 4)), "div.s $f0, $f2, $f4");
+        assert_eq!(shown(fop(Cop1Funct::CLt, 0, 1, 2)), "c.lt.s $f1, $f2");
+        assert_eq!(shown(mtc1(Reg::T0, 12)), "mtc1 $t0, $f12");
+        assert_eq!(shown(bc1(true, -4)), "bc1t -4");
+        assert_eq!(shown(syscall()), "syscall");
+        assert_eq!(shown(Insn(0xFFFF_FFFF)), ".word 0xffffffff");
+    }
+
+    #[test]
+    fn endianness_orders_bytes() {
+        let mut program = li(Reg::T0, 0x1122_3344).to_vec();
+        program.extend(li(Reg::T1, 0x1001_0000));
+        program.push(i(Opcode::Swc1, Reg::Zero, Reg::T1, 0));
+        program.insert(2, mtc1(Reg::T0, 0));
+        for (endianness, bytes, halves) in [
+            (Endianness::Big, [0x11, 0x22, 0x33, 0x44], [0x1122, 0x3344]),
+            (
+                Endianness::Little,
+                [0x44, 0x33, 0x22, 0x11],
+                [0x3344, 0x1122],
+            ),
+        ] {
+            let mut c = Computer::new(program.clone());
+            c.set_endianness(endianness);
+            c.run().unwrap();
+            assert_eq!([0, 1, 2, 3].map(|n| c.load_byte(0x1001_0000 + n)), bytes);
+            assert_eq!(
+                [0, 2].map(|n| c.load_half(0x1001_0000 + n).unwrap()),
+                halves
+            );
+        }
+    }
+
+    #[test]
+    fn little_endian_strings() {
+        let mut program = li(Reg::A0, 0x1001_0000).to_vec();
+        program.push(i(Opcode::AddIU, Reg::A1, Reg::Zero, 8));
+        program.extend(li(Reg::V0, 100));
+        program.push(syscall());
+        let mut c = Computer::new(program);
+        c.set_endianness(Endianness::Little);
+        c.set_input(io::Cursor::new(b"abc".to_vec()));
+        c.run().unwrap();
+        assert_eq!(
+            c.mem().get(&0x1001_0000),
+            Some(&u32::from_le_bytes(*b"abc\0"))
+        );
+        assert_eq!(c.load_byte(0x1001_0001), b'b');
+    }
+
+    /// Input that records what had been written to `out` when it was first read
+    struct PeekingInput {
+        out: SharedBuf,
+        seen: Rc<RefCell<Option<String>>>,
+        input: io::Cursor<Vec<u8>>,
+    }
+
+    impl io::Read for PeekingInput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            io::Read::read(&mut self.input, buf)
+        }
+    }
+
+    impl BufRead for PeekingInput {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            self.seen
+                .borrow_mut()
+                .get_or_insert_with(|| self.out.contents());
+            self.input.fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.input.consume(amt)
+        }
+    }
+
+    #[test]
+    fn reads_flush_prompts_first() {
+        let mut program = li(Reg::T0, 1.5f32.to_bits()).to_vec();
+        program.push(mtc1(Reg::T0, 12));
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 2));
+        program.push(syscall());
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 5));
+        program.push(syscall());
+        let (out, seen) = (SharedBuf::default(), Rc::default());
+        let mut c = Computer::new(program);
+        c.set_output(io::BufWriter::new(out.clone()));
+        c.set_input(PeekingInput {
+            out: out.clone(),
+            seen: Rc::clone(&seen),
+            input: io::Cursor::new(b"7\n".to_vec()),
+        });
+        c.run().unwrap();
+        assert_eq!(seen.borrow().as_deref(), Some("1.5"));
+        assert_eq!(c.ru(Reg::V0), 7);
+    }
+
+    #[test]
+    fn flush_syscall() {
+        let mut program = li(Reg::T0, 2.0f32.to_bits()).to_vec();
+        program.push(mtc1(Reg::T0, 12));
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 2));
+        program.push(syscall());
+        let out = SharedBuf::default();
+        let mut c = Computer::new(program.clone());
+        c.set_output(io::BufWriter::new(out.clone()));
+        c.run().unwrap();
+        assert_eq!(out.contents(), "");
+
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 105));
+        program.push(syscall());
+        let mut c = Computer::new(program);
+        c.set_output(io::BufWriter::new(out.clone()));
+        c.run().unwrap();
+        assert_eq!(out.contents(), "2.0");
+    }
+
+    fn mul(a: i32, b: i32, strict: bool) -> Result<u32, InsnError> {
+        let mut program = li(Reg::T0, a as u32).to_vec();
+        program.extend(li(Reg::T1, b as u32));
+        program.push(Insn(
+            (Opcode::Special2 as u32) << 26
+                | (Reg::T0 as u32) << 21
+                | (Reg::T1 as u32) << 16
+                | (Reg::T2 as u32) << 11
+                | Special2Funct::Mul as u32,
+        ));
+        let mut c = Computer::new(program);
+        c.set_strict_mul(strict);
+        c.run().map_err(|e| e.error)?;
+        Ok(c.ru(Reg::T2))
+    }
+
+    #[test]
+    fn mul_truncates_by_default() {
+        assert_eq!(mul(-6, 7, false).unwrap(), -42i32 as u32);
+        assert_eq!(mul(0x10000, 0x10001, false).unwrap(), 0x10000);
+    }
+
+    #[test]
+    fn strict_mul_overflow() {
+        assert_eq!(mul(-6, 7, true).unwrap(), -42i32 as u32);
+        assert_eq!(mul(-0x8000, 0x10000, true).unwrap(), i32::MIN as u32);
+        assert!(matches!(
+            mul(0x10000, 0x10001, true),
+            Err(InsnError::IntegerOverflow)
+        ));
+        assert!(matches!(
+            mul(0x8000, 0x10000, true),
+            Err(InsnError::IntegerOverflow)
+        ));
+    }
+
+    #[test]
+    fn text_segment_is_read_only() {
+        let program = self_modifying_program();
+        let mut c = Computer::new(program.clone());
+        let err = c.run().unwrap_err();
+        assert!(matches!(
+            err.error,
+            InsnError::WriteToTextSegment(addr) if addr == TEXT_BASE + 24
+        ));
+
+        let mut c = Computer::new(program);
+        c.set_self_modifying(true);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 9);
+        assert!(c.mem().is_empty());
+    }
+
+    #[test]
+    fn detect_stale_code() {
+        let program = self_modifying_program();
+        let builder = || ComputerBuilder::new().self_modifying(true);
+
+        let mut c = builder().detect_stale_code(true).build(program.clone());
+        let err = c.run().unwrap_err();
+        assert!(matches!(
+            err.error,
+            InsnError::StaleInstructionCache(addr) if addr == TEXT_BASE + 24
+        ));
+        assert_eq!(c.ru(Reg::T1), 0);
+
+        let mut c = builder().build(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 9);
+    }
+
+    #[test]
+    fn reset_forgets_stale_code() {
+        // Overwrites the first instruction after it has run
+        let mut program = vec![i(Opcode::AddIU, Reg::T1, Reg::T1, 1)];
+        program.extend(li(Reg::T0, i(Opcode::AddIU, Reg::T1, Reg::Zero, 9).0));
+        program.push(mtc1(Reg::T0, 0));
+        program.extend(li(Reg::T2, TEXT_BASE));
+        program.push(i(Opcode::Swc1, Reg::Zero, Reg::T2, 0));
+        let mut c = ComputerBuilder::new()
+            .self_modifying(true)
+            .detect_stale_code(true)
+            .build(program);
+        c.run().unwrap();
+        c.reset();
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 1);
+    }
+
+    #[test]
+    fn reset_restores_patched_code() {
+        let program = self_modifying_program();
+        let mut c = ComputerBuilder::new()
+            .self_modifying(true)
+            .build(program.clone());
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 9);
+        c.reset();
+        assert_eq!(c.load_word(TEXT_BASE + 24).unwrap(), program[6].0);
+        // Stop before the store, so the loaded addiu is what runs
+        c.set_pc(TEXT_BASE + 24).unwrap();
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 1);
+    }
+
+    #[test]
+    fn peek_then_step() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 5),
+            i(Opcode::AddIU, Reg::T0, Reg::T0, 1),
+        ];
+        let mut c = Computer::new(program.clone());
+        assert_eq!(c.peek_insn(), Some(program[0]));
+        assert_eq!(c.peek_insn(), Some(program[0]));
+        assert_eq!(c.executed(), 0);
+        c.step().unwrap();
+        assert_eq!(c.ru(Reg::T0), 5);
+        assert_eq!(c.peek_insn(), Some(program[1]));
+        c.step().unwrap();
+        assert_eq!(c.peek_insn(), None);
+    }
+
+    #[test]
+    fn cycle_costs() {
+        let mut program = li(Reg::T0, 0x1001_0000).to_vec();
+        program.push(i(Opcode::Lwc1, Reg::Zero, Reg::T0, 0));
+        program.push(i(Opcode::Lwc1, Reg::Zero, Reg::T0, 4));
+        program.push(r(Funct::AddU, Reg::T1, Reg::T0, Reg::T0, 0));
+
+        let mut c = Computer::new(program.clone());
+        c.run().unwrap();
+        assert_eq!(c.cycles(), c.executed());
+
+        let mut costs = CycleCosts::default();
+        costs.set_opcode(Opcode::Lwc1, 3);
+        costs.set_funct(Funct::AddU, 2);
+        let mut c = Computer::new(program);
+        c.set_cycle_costs(costs);
+        c.run().unwrap();
+        // lui and ori at 1 each, two loads at 3, addu at 2
+        assert_eq!(c.cycles(), 10);
+    }
+
+    #[test]
+    fn cancel_from_another_thread() {
+        // bgez $zero spins on itself forever
+        let mut c = Computer::new(vec![regimm(RegImm::BGeZ, Reg::Zero, -1)]);
+        let flag = Arc::new(AtomicBool::new(false));
+        c.set_cancel_flag(Arc::clone(&flag));
+        let setter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            flag.store(true, Ordering::Relaxed);
+        });
+        let err = c.run().unwrap_err();
+        setter.join().unwrap();
+        assert!(matches!(err.error, InsnError::Cancelled));
+        assert!(err.executed > 0);
+        assert_eq!(c.pc_addr(), TEXT_BASE);
+    }
+
+    fn rdhwr(rt: Reg, hw: u32) -> Insn {
+        Insn(
+            (Opcode::Special3 as u32) << 26
+                | (rt as u32) << 16
+                | hw << 11
+                | Special3Funct::RdHwr as u32,
+        )
+    }
+
+    #[test]
+    fn rdhwr_counts_cycles() {
+        let (mut program, _) = counting_loop();
+        program.insert(0, rdhwr(Reg::S0, 2));
+        program.push(rdhwr(Reg::S1, 2));
+        program.push(rdhwr(Reg::S2, 0));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::S0), 0);
+        assert_eq!(c.ru(Reg::S1), c.executed() as u32 - 2);
+        assert!(c.ru(Reg::S1) > c.ru(Reg::S0));
+        assert_eq!(c.ru(Reg::S2), 0);
+        assert_eq!(decode(rdhwr(Reg::T0, 2).0).to_string(), "rdhwr $t0, $2");
+    }
+
+    #[test]
+    fn rdhwr_rejects_unknown_registers() {
+        let err = Computer::new(vec![rdhwr(Reg::T0, 29)]).run().unwrap_err();
+        assert!(matches!(err.error, InsnError::InvalidHardwareRegister(29)));
+    }
+
+    #[test]
+    fn parse_registers() {
+        assert_eq!(parse_register("$t0").unwrap(), Register::Gpr(Reg::T0));
+        assert_eq!(parse_register("$31").unwrap(), Register::Gpr(Reg::RA));
+        assert_eq!(parse_register("$zero").unwrap(), Register::Gpr(Reg::Zero));
+        assert_eq!(parse_register("$s8").unwrap(), Register::Gpr(Reg::FP));
+        assert_eq!(parse_register("$f12").unwrap(), Register::Fpr(12));
+        assert_eq!(parse_register("$f0").unwrap(), Register::Fpr(0));
+        for bad in ["$f32", "$32", "t0", "$f", "$f012", "$+1", "$tt0", ""] {
+            assert!(parse_register(bad).is_err(), "{bad:?} parsed");
+        }
+    }
+
+    #[test]
+    fn load_memory_snapshot() {
+        let mut program = li(Reg::T0, 0x1001_0000).to_vec();
+        program.push(i(Opcode::Lw, Reg::T1, Reg::T0, 0));
+        program.push(i(Opcode::Lw, Reg::T2, Reg::T0, 8));
+        program.push(i(Opcode::Sw, Reg::T1, Reg::T0, 4));
+        let mut c = Computer::new(program);
+        c.load_memory(HashMap::from([(0x1001_0000, 7), (0x1001_0008, 9), (0, 1)]))
+            .unwrap();
+        c.run().unwrap();
+        assert_eq!((c.ru(Reg::T1), c.ru(Reg::T2)), (7, 9));
+        assert_eq!(c.mem().get(&0x1001_0004), Some(&7));
+        assert_eq!(
+            decode(i(Opcode::Lw, Reg::T1, Reg::T0, -8).0).to_string(),
+            "lw $t1, -8($t0)"
+        );
+
+        let before = c.mem().clone();
+        let err = c
+            .load_memory(HashMap::from([(0x1001_0002, 1)]))
+            .unwrap_err();
+        assert!(matches!(err, InsnError::UnalignedAccess(0x1001_0002)));
+        assert_eq!(c.mem(), &before);
+    }
+
+    #[test]
+    fn validate_flags_static_problems() {
+        let (program, _) = counting_loop();
+        assert_eq!(Computer::new(program).validate(), Ok(()));
+
+        let program = vec![
+            Insn(0),
+            i(Opcode::BLeZ, Reg::Zero, Reg::T0, 2),
+            i(Opcode::AddIU, Reg::Zero, Reg::T0, 1),
+            Insn(0xFFFF_FFFF),
+            regimm(RegImm::BGeZ, Reg::T0, -6),
+            i(Opcode::BGtZ, Reg::Zero, Reg::T0, 0),
+        ];
+        let at = |idx: u32| TEXT_BASE + idx * 4;
+        assert_eq!(
+            Computer::new(program).validate(),
+            Err(vec![
+                ValidationError::WriteToZero { addr: at(2) },
+                ValidationError::UnknownInstruction {
+                    addr: at(3),
+                    word: 0xFFFF_FFFF
+                },
+                ValidationError::BranchOutOfText {
+                    addr: at(4),
+                    target: at(0).wrapping_sub(4)
+                },
+            ])
+        );
+    }
+
+    fn exit2(code: i32) -> RunOutcome {
+        let mut program = li(Reg::A0, code as u32).to_vec();
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 17));
+        program.push(syscall());
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::Zero, 1));
+        Computer::new(program).run().unwrap()
+    }
+
+    #[test]
+    fn exit2_status() {
+        let outcome = exit2(42);
+        assert_eq!(
+            outcome,
+            RunOutcome {
+                exit_code: 42,
+                executed: 4
+            }
+        );
+        assert_eq!(ExitCode::from(outcome), ExitCode::from(42));
+        assert_eq!(ExitCode::from(exit2(300)), ExitCode::from(255));
+        assert_eq!(ExitCode::from(exit2(-1)), ExitCode::from(0));
+        assert_eq!(Computer::new(vec![]).run().unwrap().exit_code, 0);
+    }
+
+    #[test]
+    fn scan_syscalls() {
+        let mut program = vec![i(Opcode::AddIU, Reg::V0, Reg::Zero, 1), syscall()];
+        program.extend(li(Reg::V0, 17));
+        program.push(syscall());
+        program.push(i(Opcode::Lw, Reg::V0, Reg::SP, 0));
+        program.push(syscall());
+        assert_eq!(
+            scanned_syscalls(&program),
+            HashSet::from([
+                ScannedSyscall::Code(1),
+                ScannedSyscall::Code(17),
+                ScannedSyscall::Unknown
+            ])
+        );
+
+        // The branch can land on the syscall with either code in $v0
+        let program = vec![
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 5),
+            i(Opcode::BLeZ, Reg::Zero, Reg::T0, 1),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 12),
+            syscall(),
+        ];
+        assert_eq!(
+            scanned_syscalls(&program),
+            HashSet::from([ScannedSyscall::Unknown])
+        );
+
+        let program = vec![
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 5),
+            Insn::jump(Opcode::J, TEXT_BASE + 12),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 12),
+            syscall(),
+        ];
+        assert_eq!(
+            scanned_syscalls(&program),
+            HashSet::from([ScannedSyscall::Unknown])
+        );
+
+        // The syscall runs after f returns, with f's $v0
+        let program = vec![
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 1),
+            Insn::jump(Opcode::Jal, TEXT_BASE + 12),
+            syscall(),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 10),
+            Insn::reg(Funct::Jr, Reg::Zero, Reg::RA, Reg::Zero, 0),
+        ];
+        assert_eq!(
+            scanned_syscalls(&program),
+            HashSet::from([ScannedSyscall::Unknown])
+        );
+    }
+
+    #[test]
+    fn display_nop() {
+        assert_eq!(decode(0).to_string(), "nop");
+        let shifted = r(Funct::Sll, Reg::Zero, Reg::Zero, Reg::Zero, 1);
+        assert_eq!(decode(shifted.0).to_string(), "sll $zero, $zero, 1");
+    }
+
+    #[test]
+    fn zero_writes_ignored_by_default() {
+        let program = vec![
+            Insn(0),
+            i(Opcode::AddIU, Reg::Zero, Reg::Zero, 5),
+            r(Funct::AddU, Reg::T0, Reg::Zero, Reg::Zero, 0),
+        ];
+        let mut c = Computer::new(program.clone());
+        c.run().unwrap();
+        assert_eq!((c.ru(Reg::Zero), c.ru(Reg::T0)), (0, 0));
+
+        let mut c = Computer::new(program);
+        c.set_strict_zero(true);
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::RegMutZero));
+        assert_eq!(err.executed, 0);
+        assert_eq!(c.ru(Reg::Zero), 0);
+    }
+
+    #[test]
+    fn mem_trace() {
+        // Copies three words from 0x10010000 to 0x10010100, walking $t0 down from 8
+        let mut program = li(Reg::T1, 0x1001_0000).to_vec();
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::Zero, 8));
+        program.push(r(Funct::AddU, Reg::T2, Reg::T1, Reg::T0, 0));
+        program.push(i(Opcode::Lw, Reg::T3, Reg::T2, 0));
+        program.push(i(Opcode::Sw, Reg::T3, Reg::T2, 0x100));
+        program.push(i(Opcode::AddIU, Reg::T0, Reg::T0, -4));
+        program.push(regimm(RegImm::BGeZ, Reg::T0, -5));
+        let mut c = Computer::new(program.clone());
+        c.run().unwrap();
+        assert!(c.drain_mem_trace().is_empty());
+
+        let mut c = Computer::new(program);
+        c.set_mem_trace(true);
+        c.run().unwrap();
+        let trace = c.drain_mem_trace();
+        let summary: Vec<_> = trace.iter().map(|a| (a.addr, a.is_write, a.size)).collect();
+        let expected: Vec<_> = [8, 4, 0]
+            .into_iter()
+            .flat_map(|off| [(0x1001_0000 + off, false, 4), (0x1001_0100 + off, true, 4)])
+            .collect();
+        assert_eq!(summary, expected);
+        assert_eq!((trace[0].pc, trace[1].pc), (TEXT_BASE + 16, TEXT_BASE + 20));
+        assert!(c.drain_mem_trace().is_empty());
+    }
+
+    #[test]
+    fn args_in_registers_and_memory() {
+        let program = vec![
+            i(Opcode::Lw, Reg::T0, Reg::A1, 0),
+            i(Opcode::Lw, Reg::T1, Reg::T0, 0),
+            i(Opcode::Lw, Reg::T2, Reg::A1, 8),
+        ];
+        let mut c = Computer::with_args(program, &["prog", "-v"]);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::A0), 2);
+        assert_eq!(c.ru(Reg::T1), u32::from_be_bytes(*b"prog"));
+        assert_eq!(c.ru(Reg::T2), 0);
+        let second = c.load_word(ARGV_BASE + 4).unwrap();
+        let bytes: Vec<u8> = (0..3).map(|n| c.load_byte(second + n)).collect();
+        assert_eq!(bytes, b"-v\0");
+
+        c.reset();
+        c.set_endianness(Endianness::Little);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), u32::from_le_bytes(*b"prog"));
+    }
+
+    #[test]
+    fn reg_from_str() {
+        assert_eq!("$t0".parse::<Reg>().unwrap(), Reg::T0);
+        assert_eq!("$sp".parse::<Reg>().unwrap(), Reg::SP);
+        assert_eq!("$2".parse::<Reg>().unwrap(), Reg::V0);
+        for reg in Reg::all() {
+            assert_eq!(reg.to_string().parse::<Reg>().unwrap(), reg);
+        }
+        let err = "$f1".parse::<Reg>().unwrap_err();
+        assert_eq!(err.to_string(), "unknown register \"$f1\"");
+        assert!("$bogus".parse::<Reg>().is_err());
+    }
+
+    #[test]
+    fn mem_trace_reports_values() {
+        let mut program = li(Reg::T0, 0x1001_0010).to_vec();
+        program.push(i(Opcode::AddIU, Reg::T1, Reg::Zero, -3));
+        program.push(i(Opcode::Sw, Reg::T1, Reg::T0, -8));
+        program.push(i(Opcode::Lw, Reg::T2, Reg::T0, -8));
+        let mut c = Computer::new(program);
+        c.set_mem_trace(true);
+        c.run().unwrap();
+        let store = MemAccess {
+            pc: TEXT_BASE + 12,
+            addr: 0x1001_0008,
+            value: -3i32 as u32,
+            is_write: true,
+            size: 4,
+        };
+        let load = MemAccess {
+            pc: TEXT_BASE + 16,
+            is_write: false,
+            ..store
+        };
+        assert_eq!(c.drain_mem_trace(), [store, load]);
+    }
+
+    #[test]
+    fn builder_options_take_effect() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 1),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 30),
+            syscall(),
+            i(Opcode::AddIU, Reg::Zero, Reg::T0, 1),
+        ];
+        let mut c = ComputerBuilder::new()
+            .text_base(0x1000)
+            .poison_registers(true)
+            .strict_zero(true)
+            .pc_history(2)
+            .clock(crate::clock::FixedClock(7))
+            .build(program);
+        assert_eq!(c.pc_addr(), 0x1000);
+        assert_eq!(c.ru(Reg::T1), POISON);
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::RegMutZero));
+        assert_eq!(c.ru(Reg::A0), 7);
+        assert_eq!(c.recent_pcs(), [0x1008, 0x100C]);
+    }
+
+    fn special2(funct: Special2Funct, rs: Reg, rt: Reg) -> Insn {
+        Insn((Opcode::Special2 as u32) << 26 | (rs as u32) << 21 | (rt as u32) << 16 | funct as u32)
+    }
+
+    /// Runs each multiply-accumulate on `$t0` and `$t1` loaded with the paired values, returning
+    /// HI/LO as one 64-bit value after each step
+    fn accumulate(steps: &[(Special2Funct, u32, u32)]) -> Vec<u64> {
+        let mut program = vec![];
+        for &(funct, a, b) in steps {
+            program.extend(li(Reg::T0, a));
+            program.extend(li(Reg::T1, b));
+            program.push(special2(funct, Reg::T0, Reg::T1));
+        }
+        let per_step = program.len() / steps.len();
+        let mut c = Computer::new(program);
+        (0..steps.len())
+            .map(|_| {
+                c.run_steps(per_step as u64).unwrap();
+                (c.hi() as u64) << 32 | c.lo() as u64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn multiply_accumulate() {
+        use Special2Funct::*;
+        let m = -3i32 as u32;
+        assert_eq!(
+            accumulate(&[(MAdd, 0x10000, 0x30000), (MAdd, m, 5), (MSub, m, m)]),
+            [0x3_0000_0000, 0x3_0000_0000 - 15, 0x3_0000_0000 - 24]
+        );
+        // Unsigned, $t0 = 0xFFFFFFFD is a large positive number
+        assert_eq!(
+            accumulate(&[(MAddU, m, 2), (MSubU, 1, 1)]),
+            [0x1_FFFF_FFFA, 0x1_FFFF_FFF9]
+        );
+        assert_eq!(accumulate(&[(MSub, 1, 1)]), [u64::MAX]);
+    }
+
+    #[test]
+    fn mfhi_mflo() {
+        let mut program = li(Reg::T0, 0x10000).to_vec();
+        program.push(special2(Special2Funct::MAdd, Reg::T0, Reg::T0));
+        program.push(r(Funct::MfHi, Reg::T1, Reg::Zero, Reg::Zero, 0));
+        program.push(r(Funct::MfLo, Reg::T2, Reg::Zero, Reg::Zero, 0));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!((c.ru(Reg::T1), c.ru(Reg::T2)), (1, 0));
+        let shown = |insn: Insn| decode(insn.0).to_string();
+        assert_eq!(
+            shown(special2(Special2Funct::MSubU, Reg::A0, Reg::A1)),
+            "msubu $a0, $a1"
+        );
+        assert_eq!(
+            shown(r(Funct::MfLo, Reg::V0, Reg::Zero, Reg::Zero, 0)),
+            "mflo $v0"
+        );
+    }
+
+    #[test]
+    fn lenient_decode_skips_unknown_words() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 1),
+            Insn(0xFFFF_FFFF),
+            i(Opcode::AddIU, Reg::T0, Reg::T0, 1),
+        ];
+        let err = Computer::new(program.clone()).run().unwrap_err();
+        assert!(matches!(err.error, InsnError::InvalidOpcode(0b111111)));
+
+        let mut c = ComputerBuilder::new().lenient_decode(true).build(program);
+        let outcome = c.run().unwrap();
+        assert_eq!(c.ru(Reg::T0), 2);
+        assert_eq!(outcome.executed, 3);
+        assert_eq!(c.skipped(), [TEXT_BASE + 4]);
+    }
+
+    #[test]
+    fn insn_builders() {
+        assert_eq!(
+            Insn::reg(Funct::Add, Reg::T0, Reg::T1, Reg::T2, 0),
+            Insn(0x012A_4020)
+        );
+        assert_eq!(
+            Insn::reg(Funct::Sll, Reg::T0, Reg::Zero, Reg::T1, 4),
+            Insn(0x0009_4100)
+        );
+        assert_eq!(
+            Insn::imm(Opcode::AddI, Reg::T0, Reg::T0, 1),
+            Insn(0x2108_0001)
+        );
+        assert_eq!(
+            Insn::imm(Opcode::Lw, Reg::RA, Reg::SP, -4i16 as u16),
+            Insn(0x8FBF_FFFC)
+        );
+        assert_eq!(Insn::jump(Opcode::J, 0x0040_0010), Insn(0x0810_0004));
+        assert_eq!(Insn::jump(Opcode::Jal, 0x0040_0000), Insn(0x0C10_0000));
+    }
+
+    #[test]
+    fn jumps() {
+        let program = vec![
+            Insn::jump(Opcode::Jal, TEXT_BASE + 12),
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 1),
+            Insn::jump(Opcode::J, TEXT_BASE + 20),
+            i(Opcode::AddIU, Reg::T1, Reg::Zero, 1),
+            Insn::jump(Opcode::J, TEXT_BASE + 4),
+        ];
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!((c.ru(Reg::T0), c.ru(Reg::T1)), (1, 1));
+        assert_eq!(c.ru(Reg::RA), TEXT_BASE + 4);
+        assert_eq!(
+            decode(Insn::jump(Opcode::Jal, TEXT_BASE + 12).0).to_string(),
+            "jal 0x0040000c"
+        );
+
+        let far = Insn::jump(Opcode::J, TEXT_BASE + 0x100);
+        let err = Computer::new(vec![far]).run().unwrap_err();
+        assert!(matches!(err.error, InsnError::BranchOutOfText(addr) if addr == TEXT_BASE + 0x100));
+
+        // A jal that can't land doesn't link either
+        let mut c = Computer::new(vec![Insn::jump(Opcode::Jal, TEXT_BASE + 0x100)]);
+        c.set_register(Reg::RA, 0x1234).unwrap();
+        let err = c.run().unwrap_err();
+        assert!(matches!(err.error, InsnError::BranchOutOfText(addr) if addr == TEXT_BASE + 0x100));
+        assert_eq!(c.ru(Reg::RA), 0x1234);
+        assert_eq!(
+            Computer::new(vec![far]).validate(),
+            Err(vec![ValidationError::BranchOutOfText {
+                addr: TEXT_BASE,
+                target: TEXT_BASE + 0x100
+            }])
+        );
+    }
+
+    #[test]
+    fn load_linked_store_conditional() {
+        let mut program = li(Reg::T0, DATA_BASE).to_vec();
+        program.extend([
+            i(Opcode::Ll, Reg::T1, Reg::T0, 0),
+            i(Opcode::AddIU, Reg::T1, Reg::T1, 1),
+            i(Opcode::Sc, Reg::T1, Reg::T0, 0),
+            // Broken by a store to the linked word in between
+            i(Opcode::Ll, Reg::T2, Reg::T0, 0),
+            i(Opcode::Sw, Reg::Zero, Reg::T0, 0),
+            i(Opcode::AddIU, Reg::T2, Reg::T2, 1),
+            i(Opcode::Sc, Reg::T2, Reg::T0, 0),
+            // A store elsewhere leaves the link alone
+            i(Opcode::Ll, Reg::T3, Reg::T0, 0),
+            i(Opcode::Sw, Reg::T3, Reg::T0, 4),
+            i(Opcode::Sc, Reg::T3, Reg::T0, 0),
+            // The previous sc used up the link
+            i(Opcode::Sc, Reg::T4, Reg::T0, 0),
+        ]);
+        let mut c = Computer::new(program);
+        c.load_memory(HashMap::from([(DATA_BASE, 41)])).unwrap();
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T1), 1);
+        assert_eq!(c.ru(Reg::T2), 0);
+        assert_eq!(c.ru(Reg::T3), 1);
+        assert_eq!(c.ru(Reg::T4), 0);
+        assert_eq!(c.load_word(DATA_BASE).unwrap(), 0);
+        assert_eq!(
+            decode(i(Opcode::Sc, Reg::T1, Reg::T0, 4).0).to_string(),
+            "sc $t1, 4($t0)"
+        );
+    }
+
+    #[test]
+    fn diff_two_states() {
+        let mut program = li(Reg::T0, DATA_BASE).to_vec();
+        program.push(i(Opcode::AddIU, Reg::T1, Reg::Zero, 7));
+        program.push(i(Opcode::Sw, Reg::T1, Reg::T0, 4));
+        let mut reference = Computer::new(program.clone());
+        reference.run().unwrap();
+        let mut student = Computer::new(program);
+        student.run().unwrap();
+        assert!(diff_state(&reference, &student).is_empty());
+
+        student.set_register(Reg::T1, 8).unwrap();
+        student
+            .load_memory(HashMap::from([(DATA_BASE + 4, 7), (DATA_BASE + 8, 1)]))
+            .unwrap();
+        // An explicit 0 is the same as never written
+        reference.mem.insert(DATA_BASE + 12, 0);
+        assert_eq!(
+            diff_state(&reference, &student),
+            StateDiff {
+                registers: vec![RegDiff {
+                    reg: Reg::T1,
+                    old: 7,
+                    new: 8
+                }],
+                memory: vec![WordDiff {
+                    addr: DATA_BASE + 8,
+                    old: 0,
+                    new: 1
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn read_int_retries_bad_lines() {
+        let read = |input: &str, policy| {
+            let mut c = Computer::new(vec![i(Opcode::AddIU, Reg::V0, Reg::Zero, 5), syscall()]);
+            c.set_input(io::Cursor::new(input.as_bytes().to_vec()));
+            c.set_bad_int_policy(policy);
+            c.run().map(|_| c.ri(Reg::V0)).map_err(|e| e.error)
+        };
+        assert_eq!(read("abc\n42\n", BadIntPolicy::Zero).unwrap(), 0);
+        assert_eq!(read("abc\n42\n", BadIntPolicy::Retry(1)).unwrap(), 42);
+        assert!(matches!(
+            read("abc\n4x\n42\n", BadIntPolicy::Retry(1)),
+            Err(InsnError::BadIntInput(line)) if line == "4x"
+        ));
+        // End of input while retrying still goes through the EOF policy
+        assert_eq!(read("abc\n", BadIntPolicy::Retry(3)).unwrap(), -1);
+    }
+
+    #[test]
+    fn jal_then_jr_returns_after_the_call() {
+        let jr_ra = Insn::reg(Funct::Jr, Reg::Zero, Reg::RA, Reg::Zero, 0);
+        for text_base in [TEXT_BASE, 0x0000_1000] {
+            let program = vec![
+                Insn::jump(Opcode::Jal, text_base + 16),
+                i(Opcode::AddIU, Reg::T0, Reg::T0, 1),
+                i(Opcode::AddIU, Reg::V0, Reg::Zero, 10),
+                syscall(),
+                jr_ra,
+            ];
+            let mut c = Computer::with_text_base(program, text_base);
+            c.step().unwrap();
+            assert_eq!(c.ru(Reg::RA), text_base + 4);
+            c.step().unwrap();
+            assert_eq!(c.pc_addr(), text_base + 4);
+            c.run().unwrap();
+            assert_eq!(c.ru(Reg::T0), 1);
+        }
+        assert!(Computer::new(vec![jr_ra]).validate().is_ok());
+        assert_eq!(decode(jr_ra.0).to_string(), "jr $ra");
+
+        let err = Computer::new(vec![jr_ra]).run().unwrap_err();
+        assert!(matches!(err.error, InsnError::BranchOutOfText(0)));
+    }
+
+    #[test]
+    fn print_int_matches_spim() {
+        let mut program = vec![];
+        for val in [7, -42, i32::MIN] {
+            program.extend(li(Reg::A0, val as u32));
+            program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 1));
+            program.push(syscall());
+        }
+        // What SPIM prints for the same three calls
+        assert_eq!(output_of(program), "7-42-2147483648");
+    }
+
+    #[test]
+    fn step_delta_reports_changed_registers() {
+        let mut program = li(Reg::T1, 40).to_vec();
+        program.push(Insn::reg(Funct::Add, Reg::T0, Reg::T1, Reg::T1, 0));
+        program.push(Insn::reg(Funct::Add, Reg::T0, Reg::T1, Reg::Zero, 0));
+        let mut c = Computer::new(program);
+        c.run_steps(2).unwrap();
+        assert_eq!(
+            c.step_delta().unwrap(),
+            [RegDiff {
+                reg: Reg::T0,
+                old: 0,
+                new: 80
+            }]
+        );
+        assert_eq!(
+            c.step_delta().unwrap(),
+            [RegDiff {
+                reg: Reg::T0,
+                old: 80,
+                new: 40
+            }]
+        );
+        assert!(c.finished());
+        assert_eq!(c.step_delta().unwrap(), []);
+    }
+
+    /// `$t0 - $t1` through `funct`
+    fn sub(funct: Funct, a: i32, b: i32) -> Result<u32, InsnError> {
+        let mut program = li(Reg::T0, a as u32).to_vec();
+        program.extend(li(Reg::T1, b as u32));
+        program.push(Insn::reg(funct, Reg::T2, Reg::T0, Reg::T1, 0));
+        let mut c = Computer::new(program);
+        c.run().map_err(|e| e.error)?;
+        Ok(c.ru(Reg::T2))
+    }
+
+    #[test]
+    fn sub_int_min_minus_one_overflows() {
+        assert!(matches!(
+            sub(Funct::Sub, i32::MIN, 1),
+            Err(InsnError::IntegerOverflow)
+        ));
+    }
+
+    #[test]
+    fn sub_zero_minus_int_min_overflows() {
+        assert!(matches!(
+            sub(Funct::Sub, 0, i32::MIN),
+            Err(InsnError::IntegerOverflow)
+        ));
+    }
+
+    #[test]
+    fn sub_just_inside_range() {
+        assert_eq!(sub(Funct::Sub, i32::MIN, 0).unwrap(), i32::MIN as u32);
+        assert_eq!(sub(Funct::Sub, -1, i32::MIN).unwrap(), i32::MAX as u32);
+        assert_eq!(sub(Funct::Sub, -1, i32::MAX).unwrap(), i32::MIN as u32);
+    }
+
+    #[test]
+    fn subu_wraps_at_the_same_edges() {
+        assert_eq!(sub(Funct::SubU, i32::MIN, 1).unwrap(), i32::MAX as u32);
+        assert_eq!(sub(Funct::SubU, 0, i32::MIN).unwrap(), i32::MIN as u32);
+        assert_eq!(sub(Funct::SubU, 0, 1).unwrap(), u32::MAX);
+    }
+
+    #[test]
+    fn mars_register_dump() {
+        let mut program = li(Reg::T0, 0xFFFF_FFF6).to_vec();
+        program.push(i(Opcode::AddIU, Reg::RA, Reg::Zero, 42));
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        let dump = c.dump_registers_mars(Radix::Hex);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 35);
+        assert_eq!(
+            lines[..10],
+            [
+                "$zero\t0x00000000",
+                "$at\t0x00000000",
+                "$v0\t0x00000000",
+                "$v1\t0x00000000",
+                "$a0\t0x00000000",
+                "$a1\t0x00000000",
+                "$a2\t0x00000000",
+                "$a3\t0x00000000",
+                "$t0\t0xfffffff6",
+                "$t1\t0x00000000",
+            ]
+        );
+        assert_eq!(
+            lines[31..],
+            [
+                "$ra\t0x0000002a",
+                "pc\t0x0040000c",
+                "hi\t0x00000000",
+                "lo\t0x00000000",
+            ]
+        );
+        let dump = c.dump_registers_mars(Radix::Decimal);
+        assert_eq!(dump.lines().nth(8), Some("$t0\t-10"));
+        assert!(dump.ends_with("$ra\t42\npc\t4194316\nhi\t0\nlo\t0\n"));
+    }
+
+    #[test]
+    fn print_char_bytes_or_unicode() {
+        let print = |chars: &[u32], unicode: bool| {
+            let mut program = vec![i(Opcode::AddIU, Reg::V0, Reg::Zero, 11)];
+            for &ch in chars {
+                program.extend(li(Reg::A0, ch));
+                program.push(syscall());
+            }
+            let out = SharedBuf::default();
+            let mut c = ComputerBuilder::new()
+                .unicode_print_char(unicode)
+                .build(program);
+            c.set_output(out.clone());
+            c.run().unwrap();
+            let bytes = out.0.borrow().clone();
+            bytes
+        };
+        assert_eq!(print(&['A' as u32, 0x1F600], false), [b'A', 0x00]);
+        assert_eq!(print(&[0x1F600, 'é' as u32], true), "😀é".as_bytes());
+        assert_eq!(print(&[0xD800], true), "\u{FFFD}".as_bytes());
+    }
+
+    /// Prints the string at `addr` after loading `text` at `DATA_BASE`, null-padded to whole words
+    fn print_string(text: &[u8], addr: u32, endianness: Endianness) -> String {
+        let mut program = li(Reg::A0, addr).to_vec();
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, 4));
+        program.push(syscall());
+        let out = SharedBuf::default();
+        let mut c = ComputerBuilder::new().endianness(endianness).build(program);
+        c.set_output(out.clone());
+        c.load_memory(string_words(text, endianness)).unwrap();
+        c.run().unwrap();
+        out.contents()
+    }
+
+    /// `text` packed into words at `DATA_BASE`
+    fn string_words(text: &[u8], endianness: Endianness) -> HashMap<u32, u32> {
+        let words = text.chunks(4).enumerate().map(|(n, chunk)| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            let word = match endianness {
+                Endianness::Big => u32::from_be_bytes(word),
+                Endianness::Little => u32::from_le_bytes(word),
+            };
+            (DATA_BASE + n as u32 * 4, word)
+        });
+        words.collect()
+    }
+
+    #[test]
+    fn print_string_null_in_each_byte() {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            for text in ["abcd", "abcde", "abcdef", "abcdefg"] {
+                let bytes = [text.as_bytes(), b"\0xyz"].concat();
+                assert_eq!(print_string(&bytes, DATA_BASE, endianness), text);
+                // Starting mid-word
+                assert_eq!(print_string(&bytes, DATA_BASE + 1, endianness), text[1..]);
+            }
+        }
+        assert_eq!(print_string(b"\0abc", DATA_BASE, Endianness::Big), "");
+    }
+
+    #[test]
+    fn print_long_string() {
+        let text = "0123456789abcdef".repeat(4096);
+        assert_eq!(
+            print_string(text.as_bytes(), DATA_BASE + 3, Endianness::Big),
+            text[3..]
+        );
+    }
+
+    /// Times `load_string` on a 1 MiB string against reading it a byte at a time. Run with
+    /// `cargo test --release bench_load_string -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_load_string() {
+        let text = "0123456789abcdef".repeat(1 << 16);
+        let mut c = Computer::new(vec![]);
+        c.load_memory(string_words(text.as_bytes(), Endianness::Big))
+            .unwrap();
+        let time = |load: &dyn Fn() -> usize| {
+            let start = Instant::now();
+            for _ in 0..10 {
+                assert_eq!(load(), text.len());
+            }
+            start.elapsed() / 10
+        };
+        let words = time(&|| c.load_string(DATA_BASE).len());
+        let bytes = time(&|| {
+            (DATA_BASE..)
+                .map(|addr| c.load_byte(addr))
+                .take_while(|&b| b != 0)
+                .count()
+        });
+        println!("load_string: {words:?}, byte at a time: {bytes:?}");
+    }
+
+    #[test]
+    fn sync_is_a_nop() {
+        let sync = Insn(Funct::Sync as u32);
+        let program = vec![sync, i(Opcode::AddIU, Reg::T0, Reg::Zero, 1), sync];
+        let mut c = Computer::new(program.clone());
+        c.run().unwrap();
+        assert_eq!((c.ru(Reg::T0), c.executed()), (1, 3));
+        assert!(Computer::new(program).validate().is_ok());
+        assert_eq!(decode(sync.0).to_string(), "sync");
+    }
+
+    #[test]
+    fn syscall_tables() {
+        // $v0 = 30 with $a0 = 7
+        let program = vec![
+            i(Opcode::AddIU, Reg::A0, Reg::Zero, 7),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 30),
+            syscall(),
+        ];
+        let run = |table: SyscallTable| {
+            let out = SharedBuf::default();
+            let mut c = ComputerBuilder::new()
+                .syscall_table(table)
+                .clock(crate::clock::FixedClock(1234))
+                .build(program.clone());
+            c.set_output(out.clone());
+            c.run().map_err(|e| e.error)?;
+            Ok::<_, InsnError>((c.ru(Reg::A0), out.contents()))
+        };
+        assert_eq!(run(SyscallTable::mars()).unwrap(), (1234, String::new()));
+        assert!(matches!(
+            run(SyscallTable::spim()),
+            Err(InsnError::UnsupportedSyscall(30))
+        ));
+        let mut custom = SyscallTable::spim();
+        custom.insert(30, SyscallCode::PrintInt);
+        assert_eq!(run(custom).unwrap(), (7, "7".to_string()));
+    }
+
+    /// Advances a millisecond every time it's read
+    #[derive(Debug, Default)]
+    struct TickingClock(std::cell::Cell<u64>);
+
+    impl Clock for TickingClock {
+        fn now_millis(&self) -> u64 {
+            self.0.set(self.0.get() + 1);
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn run_with_timeout() {
+        let spin = vec![Insn::jump(Opcode::J, TEXT_BASE)];
+        let mut c = ComputerBuilder::new()
+            .clock(TickingClock::default())
+            .build(spin);
+        let err = c.run_with_timeout(Duration::from_millis(5)).unwrap_err();
+        assert!(matches!(err.error, InsnError::Timeout));
+        // Started at 1, so the deadline is 6 and the 6th check is the first past it
+        assert_eq!(err.executed, 6 * TIMEOUT_CHECK_INTERVAL);
+
+        let (program, _) = counting_loop();
+        let mut c = ComputerBuilder::new()
+            .clock(TickingClock::default())
+            .build(program);
+        let outcome = c.run_with_timeout(Duration::from_millis(1)).unwrap();
+        assert_eq!(outcome.exit_code, 0);
+    }
+
+    #[test]
+    fn opcode_table_is_complete() {
+        let mut listed = 0;
+        for number in 0..64 {
+            let word = number << 26;
+            let Ok(opcode) = Insn(word).opcode() else {
+                continue;
+            };
+            listed += 1;
+            assert_eq!(opcode as u32, number);
+            assert_eq!(Opcode::try_from_primitive(number).unwrap(), opcode);
+            if !opcode.implemented() {
+                assert_eq!(decode(word), DecodedInsn::Unknown(word));
+                let err = Computer::new(vec![Insn(word)]).run().unwrap_err();
+                assert!(matches!(err.error, InsnError::UnimplementedOpcode(o) if o == opcode));
+            }
+        }
+        // MIPS-I's 38 plus special2, special3, ll and sc
+        assert_eq!(listed, 42);
+        assert_eq!(Insn(0x1000_0000).opcode().unwrap(), Opcode::BEq);
+        assert_eq!(Insn(0xA000_0000).opcode().unwrap(), Opcode::Sb);
+        assert!(Insn(0xBC00_0000).opcode().is_err());
+    }
+
+    #[test]
+    fn record_and_replay_io() {
+        // Reads two ints and prints their sum
+        let program = vec![
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 5),
+            syscall(),
+            Insn::reg(Funct::AddU, Reg::T0, Reg::V0, Reg::Zero, 0),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 5),
+            syscall(),
+            Insn::reg(Funct::AddU, Reg::A0, Reg::T0, Reg::V0, 0),
+            i(Opcode::AddIU, Reg::V0, Reg::Zero, 1),
+            syscall(),
+        ];
+        let out = SharedBuf::default();
+        let mut c = Computer::new(program.clone());
+        c.set_input(io::Cursor::new(b"12\n30\n".to_vec()));
+        c.set_output(out.clone());
+        c.record_io();
+        c.run().unwrap();
+        let log = c.take_io_log().unwrap();
+        assert_eq!(
+            log.events,
+            [
+                IoEvent::Read(b"12\n30\n".to_vec()),
+                IoEvent::Wrote(b"42".to_vec())
+            ]
+        );
+        assert_eq!(out.contents(), "42");
+
+        let replayed = SharedBuf::default();
+        let mut c = Computer::new(program);
+        c.set_output(replayed.clone());
+        c.replay_io(&log);
+        c.run().unwrap();
+        assert_eq!(replayed.contents(), "42");
+        assert_eq!(log.output(), b"42");
+    }
+
+    #[test]
+    fn strict_memory_rejects_unwritten_words() {
+        let mut program = li(Reg::T0, DATA_BASE).to_vec();
+        program.extend([
+            i(Opcode::Sw, Reg::T0, Reg::T0, 0),
+            i(Opcode::Lw, Reg::T1, Reg::T0, 0),
+            i(Opcode::Lw, Reg::T2, Reg::T0, 8),
+            i(Opcode::Lw, Reg::T3, Reg::T0, 4),
+        ]);
+        let mut c = ComputerBuilder::new()
+            .strict_memory(true)
+            .build(program.clone());
+        c.load_memory(HashMap::from([(DATA_BASE + 8, 5)])).unwrap();
+        let err = c.run().unwrap_err();
+        assert!(matches!(
+            err.error,
+            InsnError::UninitializedMemoryRead(addr) if addr == DATA_BASE + 4
+        ));
+        assert_eq!((c.ru(Reg::T1), c.ru(Reg::T2)), (DATA_BASE, 5));
+
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        assert_eq!(c.ru(Reg::T3), 0);
+    }
+
+    #[test]
+    fn step_verbose_disassembles() {
+        let mut c = Computer::new(li(Reg::T0, 0x1234_5678).to_vec());
+        let mut steps = vec![];
+        loop {
+            let (text, result) = c.step_verbose().unwrap();
+            steps.push(text);
+            if result == StepResult::Exited {
+                break;
+            }
+        }
+        assert_eq!(steps, ["lui $t0, 0x1234", "ori $t0, $t0, 0x5678"]);
+        assert_eq!(
+            c.step_verbose().unwrap(),
+            (String::new(), StepResult::Exited)
+        );
+    }
+
+    #[test]
+    fn set_pc_then_step() {
+        let program = vec![
+            i(Opcode::AddIU, Reg::T0, Reg::Zero, 1),
+            i(Opcode::AddIU, Reg::T1, Reg::Zero, 2),
+            i(Opcode::AddIU, Reg::T2, Reg::Zero, 3),
+        ];
+        let mut c = Computer::new(program);
+        c.set_pc(TEXT_BASE + 8).unwrap();
+        c.step().unwrap();
+        assert_eq!((c.ru(Reg::T0), c.ru(Reg::T2)), (0, 3));
+        assert!(c.finished());
+        c.set_pc(TEXT_BASE + 4).unwrap();
+        assert!(!c.finished());
+        assert_eq!(c.peek_insn(), Some(i(Opcode::AddIU, Reg::T1, Reg::Zero, 2)));
+
+        assert!(matches!(
+            c.set_pc(TEXT_BASE + 2),
+            Err(InsnError::UnalignedAccess(_))
+        ));
+        for outside in [TEXT_BASE + 12, TEXT_BASE - 4] {
+            assert!(matches!(
+                c.set_pc(outside),
+                Err(InsnError::BranchOutOfText(pc)) if pc == outside
+            ));
+        }
+        assert_eq!(c.pc_addr(), TEXT_BASE + 4);
+    }
+
+    /// Answers every dialog the same way and remembers what it was asked
+    #[derive(Debug, Default)]
+    struct StubDialogs(Rc<RefCell<Vec<String>>>);
+
+    impl Dialogs for StubDialogs {
+        fn confirm(&mut self, _: &mut Console, message: &str) -> io::Result<Confirm> {
+            self.0.borrow_mut().push(message.to_string());
+            Ok(Confirm::No)
+        }
+
+        fn input_int(&mut self, _: &mut Console, message: &str) -> io::Result<IntInput> {
+            self.0.borrow_mut().push(message.to_string());
+            Ok(IntInput::Ok(-7))
+        }
+
+        fn message(&mut self, _: &mut Console, message: &str, kind: MessageKind) -> io::Result<()> {
+            self.0.borrow_mut().push(format!("{kind:?}: {message}"));
+            Ok(())
+        }
+    }
+
+    /// `code` with `$a0` pointing at `"Sure?"` and `$a1` = 2
+    fn dialog(code: u32, dialogs: impl Dialogs + 'static, input: &str) -> (Computer, String) {
+        let mut program = li(Reg::A0, DATA_BASE).to_vec();
+        program.push(i(Opcode::AddIU, Reg::A1, Reg::Zero, 2));
+        program.push(i(Opcode::AddIU, Reg::V0, Reg::Zero, code as i32));
+        program.push(syscall());
+        let out = SharedBuf::default();
+        let mut c = ComputerBuilder::new().dialogs(dialogs).build(program);
+        c.set_output(out.clone());
+        c.set_input(io::Cursor::new(input.as_bytes().to_vec()));
+        let message = HashMap::from([
+            (DATA_BASE, u32::from_be_bytes(*b"Sure")),
+            (DATA_BASE + 4, u32::from_be_bytes(*b"?\0\0\0")),
+        ]);
+        c.load_memory(message).unwrap();
+        c.run().unwrap();
+        (c, out.contents())
+    }
+
+    #[test]
+    fn dialogs_use_the_provider() {
+        let asked = Rc::new(RefCell::new(vec![]));
+        let (c, out) = dialog(50, StubDialogs(asked.clone()), "");
+        assert_eq!((c.ru(Reg::A0), out), (Confirm::No as u32, String::new()));
+        let (c, _) = dialog(51, StubDialogs(asked.clone()), "");
+        assert_eq!((c.ri(Reg::A0), c.ri(Reg::A1)), (-7, 0));
+        dialog(55, StubDialogs(asked.clone()), "");
+        assert_eq!(*asked.borrow(), ["Sure?", "Sure?", "Warning: Sure?"]);
+    }
+
+    #[test]
+    fn dialogs_default_to_the_console() {
+        let (c, out) = dialog(50, ConsoleDialogs, "y\n");
+        assert_eq!((c.ru(Reg::A0), out.as_str()), (0, "Sure? [y/n] "));
+        let (c, _) = dialog(50, ConsoleDialogs, "");
+        assert_eq!(c.ru(Reg::A0), Confirm::Cancel as u32);
+        let (c, _) = dialog(51, ConsoleDialogs, "x\n");
+        assert_eq!(c.ri(Reg::A1), -1);
+        let (_, out) = dialog(55, ConsoleDialogs, "");
+        assert_eq!(out, "warning: Sure?\n");
+    }
+
+    fn bitfield(funct: Special3Funct, rt: Reg, rs: Reg, msb: u32, lsb: u32) -> Insn {
+        Insn(
+            (Opcode::Special3 as u32) << 26
+                | (rs as u32) << 21
+                | (rt as u32) << 16
+                | msb << 11
+                | lsb << 6
+                | funct as u32,
+        )
+    }
+
+    /// `$t1` after `insn` runs with `$t0` = `src` and `$t1` = `dst`
+    fn run_bitfield(insn: Insn, src: u32, dst: u32) -> u32 {
+        let mut program = li(Reg::T0, src).to_vec();
+        program.extend(li(Reg::T1, dst));
+        program.push(insn);
+        let mut c = Computer::new(program);
+        c.run().unwrap();
+        c.ru(Reg::T1)
+    }
+
+    #[test]
+    fn ext_extracts_a_field() {
+        let ext =
+            |size: u32, pos: u32| bitfield(Special3Funct::Ext, Reg::T1, Reg::T0, size - 1, pos);
+        // Bits 8-15
+        assert_eq!(run_bitfield(ext(8, 8), 0x12AB_CD34, 0xFFFF_FFFF), 0xCD);
+        assert_eq!(run_bitfield(ext(1, 31), 0x8000_0000, 0), 1);
+        assert_eq!(run_bitfield(ext(32, 0), 0xDEAD_BEEF, 0), 0xDEAD_BEEF);
+        // Runs past bit 31, so only bits 28-31 are taken
+        assert_eq!(run_bitfield(ext(8, 28), 0xA000_0000, 0), 0xA);
+        assert_eq!(decode(ext(8, 4).0).to_string(), "ext $t1, $t0, 4, 8");
+    }
+
+    #[test]
+    fn ins_inserts_into_a_field() {
+        let ins = |size: u32, pos: u32| {
+            bitfield(Special3Funct::Ins, Reg::T1, Reg::T0, pos + size - 1, pos)
+        };
+        assert_eq!(run_bitfield(ins(8, 8), 0xFFFF_FFAB, 0), 0x0000_AB00);
+        assert_eq!(run_bitfield(ins(4, 4), 0x5, 0xFFFF_FFFF), 0xFFFF_FF5F);
+        assert_eq!(
+            run_bitfield(ins(32, 0), 0xDEAD_BEEF, 0x1234_5678),
+            0xDEAD_BEEF
+        );
+        // msb below lsb is an empty field
+        let empty = bitfield(Special3Funct::Ins, Reg::T1, Reg::T0, 3, 4);
+        assert_eq!(run_bitfield(empty, u32::MAX, 0x1234_5678), 0x1234_5678);
+        assert_eq!(decode(ins(8, 4).0).to_string(), "ins $t1, $t0, 4, 8");
+    }
 }
@@ -0,0 +1,51 @@
+use crate::emulator::RunError;
+use crate::tokenizer::ParseError;
+
+/// Any error from going from source to a finished run, so a caller can handle them in one place.
+/// The message names the phase that failed. Assembling and linking will add their own variants
+#[derive(Debug, thiserror::Error)]
+pub enum SpimError {
+    #[error("parse error: {0}")]
+    Parse(#[from] ParseError),
+
+    #[error("runtime error: {0}")]
+    Run(#[from] RunError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Computer, Funct, Insn, InsnError, Reg};
+    use crate::tokenizer::tokenize_line;
+
+    /// Tokenizes `line`, then runs `program`
+    fn tokenize_and_run(line: &str, program: Vec<Insn>) -> Result<(), SpimError> {
+        tokenize_line(line, 1)?;
+        Computer::new(program).run()?;
+        Ok(())
+    }
+
+    #[test]
+    fn phases_are_distinguishable() {
+        let err = tokenize_and_run("add $t0 @", vec![]).unwrap_err();
+        assert!(matches!(
+            err,
+            SpimError::Parse(ParseError::UnexpectedChar { .. })
+        ));
+        assert_eq!(err.to_string(), "parse error: line 1: unexpected '@'");
+
+        let brk = Insn::reg(Funct::Break, Reg::Zero, Reg::Zero, Reg::Zero, 0);
+        let err = tokenize_and_run("break", vec![brk]).unwrap_err();
+        assert!(matches!(
+            err,
+            SpimError::Run(RunError {
+                error: InsnError::Breakpoint(0),
+                ..
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "runtime error: break 0x0 after 0 instructions"
+        );
+    }
+}
@@ -1 +1,12 @@
+pub mod api;
+pub mod clock;
+pub mod console;
+pub mod dialog;
+/// Public only with the `internals` feature, for tools that need more than `api` offers. Its
+/// interface may change in any release
+#[cfg(feature = "internals")]
+pub mod emulator;
+#[cfg(not(feature = "internals"))]
 mod emulator;
+pub mod error;
+pub mod tokenizer;
@@ -0,0 +1,442 @@
+use crate::emulator::parse_register;
+pub use crate::emulator::Register;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// One lexical element of an assembly line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// The first identifier on a line that isn't a label definition
+    Mnemonic(String),
+    Register(Register),
+    /// Decimal, `0x` hex, `0b` binary or a `'c'` character literal
+    Immediate(i64),
+    /// `name:`, without the colon
+    LabelDef(String),
+    /// Any identifier after the mnemonic
+    LabelRef(String),
+    /// `.name`, without the dot
+    Directive(String),
+    /// A `"..."` literal with its escapes resolved
+    Str(String),
+    Comma,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("line {line}: unexpected {ch:?}")]
+    UnexpectedChar { line: usize, col: usize, ch: char },
+
+    #[error("line {line}: unterminated string")]
+    UnterminatedString { line: usize },
+
+    #[error("line {line}: unknown escape \\{ch}")]
+    BadEscape { line: usize, ch: char },
+
+    #[error("line {line}: unknown register {name:?}")]
+    BadRegister {
+        line: usize,
+        col: usize,
+        name: String,
+    },
+
+    #[error("line {line}: bad number {text:?}")]
+    BadNumber {
+        line: usize,
+        col: usize,
+        text: String,
+    },
+}
+
+impl ParseError {
+    pub fn line(&self) -> usize {
+        match *self {
+            ParseError::UnexpectedChar { line, .. }
+            | ParseError::UnterminatedString { line }
+            | ParseError::BadEscape { line, .. }
+            | ParseError::BadRegister { line, .. }
+            | ParseError::BadNumber { line, .. } => line,
+        }
+    }
+
+    /// 1-based, in characters. Errors that don't point at one spot in the line have none
+    pub fn col(&self) -> Option<usize> {
+        match *self {
+            ParseError::UnexpectedChar { col, .. }
+            | ParseError::BadRegister { col, .. }
+            | ParseError::BadNumber { col, .. } => Some(col),
+            ParseError::UnterminatedString { .. } | ParseError::BadEscape { .. } => None,
+        }
+    }
+}
+
+/// `err` followed by the line of `source` it refers to and, when the error has a column, a caret
+/// under it, in the style of rustc:
+///
+/// ```text
+/// line 2: unexpected '@'
+///   |
+/// 2 | add $t0 @
+///   |         ^
+/// ```
+///
+/// Lines are 1-based. Tabs before the column are kept in the caret line so it lines up however
+/// they're displayed
+pub fn render_error(err: &ParseError, source: &str) -> String {
+    let line = err.line();
+    let mut out = err.to_string();
+    let Some(text) = line.checked_sub(1).and_then(|n| source.lines().nth(n)) else {
+        return out;
+    };
+    let gutter = " ".repeat(line.to_string().len());
+    out += &format!("\n{gutter} |\n{line} | {text}");
+    if let Some(col) = err.col() {
+        let pad: String = text
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out += &format!("\n{gutter} | {pad}^");
+    }
+    out
+}
+
+/// Splits one line of source into tokens. Spaces, tabs and commas may separate operands in any
+/// combination, as in SPIM; commas are still reported so a parser can check operand lists. `#`
+/// starts a comment. `line` is only used in errors
+pub fn tokenize_line(src: &str, line: usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = src.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        match ch {
+            '#' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ',' | '(' | ')' => {
+                chars.next();
+                tokens.push(match ch {
+                    ',' => Token::Comma,
+                    '(' => Token::LParen,
+                    _ => Token::RParen,
+                });
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Str(string(&mut chars, line)?));
+            }
+            '\'' => {
+                chars.next();
+                let ch = char_literal(src, &mut chars, line)?;
+                tokens.push(Token::Immediate(ch as i64));
+            }
+            '$' => {
+                let name = word(src, &mut chars, start);
+                let reg = parse_register(name).map_err(|_| ParseError::BadRegister {
+                    line,
+                    col: column(src, start),
+                    name: name.to_string(),
+                })?;
+                tokens.push(Token::Register(reg));
+            }
+            '.' => {
+                let name = &word(src, &mut chars, start)[1..];
+                if name.is_empty() {
+                    let col = column(src, start);
+                    return Err(ParseError::UnexpectedChar { line, col, ch });
+                }
+                tokens.push(Token::Directive(name.to_string()));
+            }
+            c if c == '-' || c == '+' || c.is_ascii_digit() => {
+                let text = word(src, &mut chars, start);
+                let val = number(text).ok_or_else(|| ParseError::BadNumber {
+                    line,
+                    col: column(src, start),
+                    text: text.to_string(),
+                })?;
+                tokens.push(Token::Immediate(val));
+            }
+            c if is_ident_start(c) => {
+                let name = word(src, &mut chars, start).to_string();
+                if chars.next_if(|&(_, c)| c == ':').is_some() {
+                    tokens.push(Token::LabelDef(name));
+                } else if tokens.iter().any(|t| !matches!(t, Token::LabelDef(_))) {
+                    tokens.push(Token::LabelRef(name));
+                } else {
+                    tokens.push(Token::Mnemonic(name));
+                }
+            }
+            _ => {
+                let col = column(src, start);
+                return Err(ParseError::UnexpectedChar { line, col, ch });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// 1-based character column of the byte offset `at`
+fn column(src: &str, at: usize) -> usize {
+    src[..at].chars().count() + 1
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+/// Identifier characters; `.` is included for mnemonics like `add.s`
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Consumes the character at `start`, which the iterator must be on, and the identifier
+/// characters after it. Returns the text consumed
+fn word<'a>(src: &'a str, chars: &mut Peekable<CharIndices>, start: usize) -> &'a str {
+    let mut end = start;
+    while let Some(&(i, c)) = chars.peek() {
+        if i != start && !is_ident(c) {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    &src[start..end]
+}
+
+fn number(text: &str) -> Option<i64> {
+    let (neg, digits) = match text.as_bytes().first()? {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let val = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if neg { -val } else { val })
+}
+
+/// The character after a backslash
+fn escape(ch: char, line: usize) -> Result<char, ParseError> {
+    Ok(match ch {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' | '"' | '\'' => ch,
+        _ => return Err(ParseError::BadEscape { line, ch }),
+    })
+}
+
+/// The rest of a string literal whose opening quote has been consumed
+fn string(chars: &mut Peekable<CharIndices>, line: usize) -> Result<String, ParseError> {
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '"')) => return Ok(out),
+            Some((_, '\\')) => {
+                let (_, ch) = chars
+                    .next()
+                    .ok_or(ParseError::UnterminatedString { line })?;
+                out.push(escape(ch, line)?);
+            }
+            Some((_, ch)) => out.push(ch),
+            None => return Err(ParseError::UnterminatedString { line }),
+        }
+    }
+}
+
+/// The rest of a character literal whose opening quote has been consumed. A missing closing
+/// quote is reported at the end of the line
+fn char_literal(
+    src: &str,
+    chars: &mut Peekable<CharIndices>,
+    line: usize,
+) -> Result<char, ParseError> {
+    let unexpected = |at: usize, ch| ParseError::UnexpectedChar {
+        line,
+        col: column(src, at),
+        ch,
+    };
+    let ch = match chars.next() {
+        Some((_, '\\')) => escape(chars.next().map_or(' ', |(_, c)| c), line)?,
+        Some((at, '\'')) => return Err(unexpected(at, '\'')),
+        None => return Err(unexpected(src.len(), '\'')),
+        Some((_, ch)) => ch,
+    };
+    match chars.next() {
+        Some((_, '\'')) => Ok(ch),
+        Some((at, other)) => Err(unexpected(at, other)),
+        None => Err(unexpected(src.len(), '\'')),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Reg;
+
+    fn gpr(reg: Reg) -> Token {
+        Token::Register(Register::Gpr(reg))
+    }
+
+    fn mnemonic(name: &str) -> Token {
+        Token::Mnemonic(name.to_string())
+    }
+
+    #[test]
+    fn messy_whitespace_and_commas() {
+        let expected = vec![mnemonic("addi"), gpr(Reg::T0), Token::Comma, gpr(Reg::T0)];
+        let tidy = tokenize_line("addi $t0, $t0, 1", 1).unwrap();
+        assert_eq!(tidy[..4], expected);
+        assert_eq!(tidy[4..], [Token::Comma, Token::Immediate(1)]);
+        assert_eq!(
+            tokenize_line("\t addi\t$t0 ,$t0,\t\t1   # bump", 1).unwrap(),
+            tidy
+        );
+        assert_eq!(
+            tokenize_line("addi $t0 $t0 1", 1).unwrap(),
+            [
+                mnemonic("addi"),
+                gpr(Reg::T0),
+                gpr(Reg::T0),
+                Token::Immediate(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_memory_operands_and_fp() {
+        assert_eq!(
+            tokenize_line("loop:\tlwc1 $f2, -8($sp)", 1).unwrap(),
+            [
+                Token::LabelDef("loop".to_string()),
+                mnemonic("lwc1"),
+                Token::Register(Register::Fpr(2)),
+                Token::Comma,
+                Token::Immediate(-8),
+                Token::LParen,
+                gpr(Reg::SP),
+                Token::RParen,
+            ]
+        );
+        assert_eq!(
+            tokenize_line("  add.s $f0,$f1,$f2", 1).unwrap()[0],
+            mnemonic("add.s")
+        );
+        assert_eq!(
+            tokenize_line("bgez $t0, loop_end", 1).unwrap()[3],
+            Token::LabelRef("loop_end".to_string())
+        );
+    }
+
+    #[test]
+    fn directives_strings_and_numbers() {
+        assert_eq!(
+            tokenize_line("msg: .asciiz \"hi\\t\\\"there\\\"\\n\"", 1).unwrap(),
+            [
+                Token::LabelDef("msg".to_string()),
+                Token::Directive("asciiz".to_string()),
+                Token::Str("hi\t\"there\"\n".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize_line(".word 0x10, -0x1, 0b101, 'a', '\\n', +3", 1).unwrap(),
+            [
+                Token::Directive("word".to_string()),
+                Token::Immediate(16),
+                Token::Comma,
+                Token::Immediate(-1),
+                Token::Comma,
+                Token::Immediate(5),
+                Token::Comma,
+                Token::Immediate(97),
+                Token::Comma,
+                Token::Immediate(10),
+                Token::Comma,
+                Token::Immediate(3),
+            ]
+        );
+        assert_eq!(tokenize_line("   # only a comment", 1).unwrap(), []);
+        assert_eq!(
+            tokenize_line(".asciiz \"# not a comment\"", 1).unwrap()[1],
+            Token::Str("# not a comment".to_string())
+        );
+    }
+
+    #[test]
+    fn unterminated_strings() {
+        assert_eq!(
+            tokenize_line(".asciiz \"hello", 3),
+            Err(ParseError::UnterminatedString { line: 3 })
+        );
+        // The closing quote is escaped, so the string never ends
+        assert_eq!(
+            tokenize_line(".asciiz \"hello\\\"", 4),
+            Err(ParseError::UnterminatedString { line: 4 })
+        );
+        // A backslash as the very last character has nothing to escape
+        assert_eq!(
+            tokenize_line(".asciiz \"hello\\", 5),
+            Err(ParseError::UnterminatedString { line: 5 })
+        );
+        assert_eq!(
+            tokenize_line(".asciiz \"say \\\"hi\\\"\"", 6).unwrap()[1],
+            Token::Str("say \"hi\"".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        assert_eq!(
+            tokenize_line("add $t0, $t9x, $t1", 5),
+            Err(ParseError::BadRegister {
+                line: 5,
+                col: 10,
+                name: "$t9x".to_string()
+            })
+        );
+        assert_eq!(
+            tokenize_line("li $t0, 12ab", 6),
+            Err(ParseError::BadNumber {
+                line: 6,
+                col: 9,
+                text: "12ab".to_string()
+            })
+        );
+        assert_eq!(
+            tokenize_line("add $t0 @", 7),
+            Err(ParseError::UnexpectedChar {
+                line: 7,
+                col: 9,
+                ch: '@'
+            })
+        );
+    }
+
+    #[test]
+    fn render_error_points_at_column() {
+        let source = "main:\n\tadd $t0, $t1 @ $t2\n";
+        let err = tokenize_line(source.lines().nth(1).unwrap(), 2).unwrap_err();
+        assert_eq!(
+            render_error(&err, source),
+            "line 2: unexpected '@'\n  |\n2 | \tadd $t0, $t1 @ $t2\n  | \t             ^"
+        );
+        // No column to point at, and a line past the end of the source
+        let err = ParseError::UnterminatedString { line: 1 };
+        assert_eq!(
+            render_error(&err, ".asciiz \"hi"),
+            "line 1: unterminated string\n  |\n1 | .asciiz \"hi"
+        );
+        let err = ParseError::UnterminatedString { line: 9 };
+        assert_eq!(render_error(&err, ""), "line 9: unterminated string");
+    }
+}